@@ -1,207 +1,435 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
-mod kem;
-mod tcp;
-mod msg;
-mod comms;
-mod save;
-
-use std::{sync::{mpsc, RwLock, Arc}, thread};
+use std::{sync::{mpsc, Arc}, thread};
 use eframe::egui;
-use once_cell::sync::Lazy;
+use whisperer_core::{tcp, msg, comms, save, discovery, peers, ipc, notify, export};
 
 const WIN_SIZE: [f32; 2] = [600.0, 400.0];
-static mut KNOWN_PEERS: Lazy<RwLock<Vec<msg::Recipient>>> = Lazy::new(|| {
-    println!("INIT DATA");
-    let mut vec: Vec<msg::Recipient> = Vec::new();
-    vec.push(msg::Recipient::from("None"));
-    RwLock::new(vec)
-});
+/// The character limit for a single message, long enough for pasted logs and stack traces
+const MAX_DRAFT_LENGTH: usize = 100_000;
 
 struct MainWindow {
     host: String,
-    chat_history: Vec<msg::ChatHistory>,
+    peers: Arc<peers::PeerStore>,
+    /// Whether the daemon listens and saves, and `peers` only mirrors its peer store
+    attached: bool,
+    /// Whether the data is sealed and its passphrase was not given yet, nothing is loaded or started until it is
+    locked: bool,
     new_event: mpsc::Sender<Event>,
     listener: mpsc::Receiver<Event>,
+    report: comms::Reporter,
+    notifier: Option<notify::Notifier>,
     current_peer: msg::Recipient,
     draft: String,
     new_alias: String,
     new_peer: String,
     thinking: bool,
-    sending: bool,
-    confirm_remove: bool
+    confirm_remove: bool,
+    identity_warning: Option<String>,
+    error: Option<String>,
+    file_path: String,
+    file_offers: Vec<comms::FileOffer>,
+    transfers: Vec<msg::Transfer>,
+    discovery: Option<discovery::Discovery>,
+    discovered: Vec<discovery::Instance>,
+    discovery_error: Option<String>,
+    settings: save::Settings,
+    new_port: String,
+    passphrase: String,
+    new_passphrase: String,
+    repeat_passphrase: String,
+    passphrase_error: Option<String>,
+    /// What went wrong while loading the data, shown until it is dismissed
+    load_problems: Vec<save::Problem>,
+    import_path: String,
+    /// Where the last export went, or how the last import went
+    history_notice: Option<String>
 }
 impl MainWindow {
     fn new(
         cc: &eframe::CreationContext<'_>,
         host: String,
+        peers: Arc<peers::PeerStore>,
+        attached: bool,
+        locked: bool,
         sender: mpsc::Sender<Event>,
         receiver: mpsc::Receiver<Event>
     ) -> Self {
+        let settings = save::get_settings();
         let ctx = cc.egui_ctx.clone();
-        let send = sender.clone();
-        thread::spawn(move || comms::request_handler_thread(ctx, send));
-
-        let first = unsafe {KNOWN_PEERS.read().unwrap().clone()};
-        let first = first.first().unwrap();
-        let first = first.clone();
+        let incoming = mpsc::Sender::clone(&sender);
+        peers.watch(move |change| {
+            if let peers::Change::Message(address, id) = change {
+                incoming.send(Event::Incoming(address, id)).unwrap_or(());
+            }
+            ctx.request_repaint();
+        });
 
-        let mut starting_history: Vec<msg::ChatHistory> = Vec::new();
-        starting_history.push(msg::ChatHistory::new(first.clone()));
+        let ctx = cc.egui_ctx.clone();
+        let send = sender.clone();
+        let report: comms::Reporter = Arc::new(move |event| {
+            send.send(Event::Network(event)).unwrap_or(());
+            ctx.request_repaint();
+        });
 
-        println!("LOAD DATA");
-        let (peers, histories) = save::get_data();
-        unsafe {
-            let mut wlock = KNOWN_PEERS.write().unwrap();
-            for peer in peers.iter() {
-                wlock.push(peer.clone());
+        let mut error: Option<String> = None;
+        if attached {
+            println!("ATTACH TO DAEMON");
+            if let Err(e) = ipc::attach(Arc::clone(&peers), Arc::clone(&report)) {
+                error = Some(format!("Could not attach to the daemon: {e}"));
             }
         }
-        for history in histories.iter() {
-            starting_history.push(history.clone());
-        }
 
         println!("INIT APP");
-        Self {
+        let mut window = Self {
             host: host.clone(),
-            chat_history: starting_history,
+            peers,
+            attached,
+            locked,
             new_event: sender,
             listener: receiver,
-            current_peer: first,
+            report,
+            notifier: notify::Notifier::session(),
+            current_peer: msg::Recipient::from("None"),
             draft: String::new(),
             new_alias: String::new(),
             new_peer: String::new(),
             thinking: false,
-            sending: false,
-            confirm_remove: false
+            confirm_remove: false,
+            identity_warning: None,
+            error,
+            file_path: String::new(),
+            file_offers: Vec::new(),
+            transfers: Vec::new(),
+            discovery: None,
+            discovered: Vec::new(),
+            discovery_error: None,
+            new_port: settings.listen_port.to_string(),
+            settings,
+            passphrase: String::new(),
+            new_passphrase: String::new(),
+            repeat_passphrase: String::new(),
+            passphrase_error: None,
+            load_problems: Vec::new(),
+            import_path: String::new(),
+            history_notice: None
+        };
+        if !attached && !locked {
+            window.start_network();
+        }
+        if window.settings.discovery {
+            window.set_discovery(true, &cc.egui_ctx);
+        }
+        window
+    }
+
+    /// Save every change as it is made, queue the messages that wait for their peer, and start listening and sending them
+    fn start_network(&self) {
+        save::keep_saved(&self.peers);
+        for history in self.peers.histories().iter() {
+            for msg in history.history().iter() {
+                if msg.author() == "You" && msg.status() == msg::MessageStatus::Queued {
+                    comms::OUTBOX.queue(history.peer().address(), msg.clone());
+                }
+            }
+        }
+
+        let handler_report = Arc::clone(&self.report);
+        let port = self.settings.listen_port;
+        let handler_peers = Arc::clone(&self.peers);
+        thread::spawn(move || comms::request_handler_thread(handler_report, port, handler_peers));
+        let outbox_report = Arc::clone(&self.report);
+        let outbox_peers = Arc::clone(&self.peers);
+        thread::spawn(move || comms::outbox_thread(outbox_report, outbox_peers));
+    }
+
+    /// Check the passphrase that was typed in and load the data with it, the result comes back as `Event::Unlocked`.
+    /// The key takes a lot of memory and time to derive, so it is done on another thread.
+    fn unlock(&mut self, ctx: &egui::Context) {
+        self.thinking = true;
+        self.passphrase_error = None;
+        let passphrase = self.passphrase.clone();
+        let sender = self.new_event.clone();
+        let update_ctx = ctx.clone();
+        thread::spawn(move || {
+            let result = save::unlock(&passphrase).map(|()| {
+                println!("LOAD DATA");
+                save::get_data()
+            });
+            sender.send(Event::Unlocked(result)).unwrap_or(());
+            update_ctx.request_repaint();
+        });
+    }
+
+    /// Seal the data with the new passphrase, or stop sealing it if `remove` is set.
+    /// The daemon seals it if there is one, and its answer ends up on the error line.
+    /// Otherwise it is sealed on another thread, and the result comes back as `Event::PassphraseChanged`.
+    fn change_passphrase(&mut self, remove: bool, ctx: &egui::Context) {
+        let current = Some(self.passphrase.clone()).filter(|_| save::encrypted());
+        let new = Some(self.new_passphrase.clone()).filter(|_| !remove);
+        self.passphrase_error = None;
+        if self.attached {
+            self.tell_daemon(ipc::Request::Passphrase {current, new});
+            self.passphrase.clear();
+            self.new_passphrase.clear();
+            self.repeat_passphrase.clear();
+            return
+        }
+
+        self.thinking = true;
+        let peers = Arc::clone(&self.peers);
+        let sender = self.new_event.clone();
+        let update_ctx = ctx.clone();
+        thread::spawn(move || {
+            let result = save::set_passphrase(current.as_deref(), new.as_deref()).map(|()| save::set_data(&peers));
+            sender.send(Event::PassphraseChanged(result)).unwrap_or(());
+            update_ctx.request_repaint();
+        });
+    }
+
+    /// Pass a change made to the peer store on to the daemon, if there is one. Failures end up on the error line.
+    fn tell_daemon(&self, request: ipc::Request) {
+        if !self.attached {return}
+        let report = Arc::clone(&self.report);
+        thread::spawn(move || if let Err(e) = ipc::request(&request) {
+            report(comms::Event::Error(String::from("The daemon did not take a change"), e));
+        });
+    }
+
+    /// Write the chat history with the peer at `address`, or with every peer, to a file in the export directory
+    fn export(&mut self, address: Option<String>, format: export::Format) {
+        let (name, histories) = match address {
+            Some(address) => (self.peers.alias(&address).unwrap_or(address.clone()), self.peers.history(&address).into_iter().collect()),
+            None => (String::from("whisperer"), self.peers.histories())
+        };
+        let path = save::export_path(&format!("{}.{}", name.replace(':', "-"), format.extension()));
+        let written = path.and_then(|path| std::fs::write(&path, export::export(&histories, format)).map(|()| path));
+        self.history_notice = Some(match written {
+            Ok(path) => format!("Exported to {}", path.to_string_lossy()),
+            Err(e) => format!("Could not export: {e}")
+        });
+    }
+
+    /// Bring in the chat histories of the JSON export at `import_path`, messages that are already here are skipped
+    fn import(&mut self) {
+        let path = self.import_path.trim().to_string();
+        let histories = match std::fs::read_to_string(&path) {
+            Ok(text) => export::from_json(&text).map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string())
+        };
+        self.history_notice = Some(match histories {
+            Ok(histories) => {
+                let added: usize = histories.iter().map(|history| self.peers.merge(history.clone())).sum();
+                self.tell_daemon(ipc::Request::Import(histories));
+                self.import_path.clear();
+                format!("Imported {added} messages")
+            },
+            Err(e) => format!("Could not import {path}: {e}")
+        });
+    }
+
+    /// Start sending `msg` to `peer`, its status is kept up to date in the peer store
+    fn send_message(&self, peer: msg::Recipient, msg: msg::Message) {
+        if self.attached {
+            return self.tell_daemon(ipc::Request::Send(peer.address(), msg))
+        }
+        let peers = Arc::clone(&self.peers);
+        let report = Arc::clone(&self.report);
+        println!("SEND MESSAGE");
+        thread::spawn(move || comms::send_message(peers, peer, msg, report));
+    }
+
+    /// Check that `ip` runs Whisperer on `port` and agree on a key with it, the result comes back as `Event::NewPeerResult`
+    fn add_peer(&mut self, ip: String, port: u16, alias: Option<String>, ctx: &egui::Context) {
+        self.thinking = true;
+        if self.peers.contains(&msg::address(&ip, port)) {
+            self.new_peer = String::from("Address already added");
+            self.thinking = false;
+            return
+        }
+
+        let sender = self.new_event.clone();
+        let update_ctx = ctx.clone();
+        let attached = self.attached;
+        thread::spawn(move || {
+            if attached {
+                sender.send(Event::NewPeerResult(ipc::add_peer(ip, port, alias))).unwrap_or(());
+                return update_ctx.request_repaint()
+            }
+            let result = tcp::check_availability((ip.as_str(), port))
+                .and_then(|_| comms::make_keypair(ip.clone(), port, None))
+                .map(|(identity, key)| {
+                    let mut rec = msg::Recipient::from(ip);
+                    rec.set_port(port);
+                    rec.set_alias(alias);
+                    rec.set_identity(Some(identity));
+                    rec.set_private_key(key);
+                    rec
+                });
+            sender.send(Event::NewPeerResult(result)).unwrap_or(());
+            update_ctx.request_repaint();
+        });
+    }
+
+    /// Turn discovery of other instances on the local network on or off
+    fn set_discovery(&mut self, enabled: bool, ctx: &egui::Context) {
+        self.discovery = None;
+        self.discovered.clear();
+        self.discovery_error = None;
+        if self.settings.discovery != enabled {
+            self.settings.discovery = enabled;
+            if let Err(e) = save::set_settings(&self.settings) {
+                self.error = Some(format!("Could not save the settings: {e}"));
+            }
+        }
+        if !enabled {return}
+
+        let announcement = discovery::Announcement {
+            name: discovery::host_name().unwrap_or(self.host.clone()),
+            port: self.settings.listen_port
+        };
+        let sender = self.new_event.clone();
+        let update_ctx = ctx.clone();
+        match discovery::Discovery::start(announcement, discovery::DISCOVERY_PORT, move |instance| {
+            sender.send(Event::PeerDiscovered(instance)).unwrap_or(());
+            update_ctx.request_repaint();
+        }) {
+            Ok(running) => self.discovery = Some(running),
+            Err(e) => self.discovery_error = Some(format!("Could not start discovery: {e}"))
+        }
+    }
+
+    /// Start sending the file at `path` to the current peer
+    fn send_file(&self, path: std::path::PathBuf) {
+        if self.attached {
+            // the daemon may run in another directory
+            let path = std::fs::canonicalize(&path).unwrap_or(path);
+            return self.tell_daemon(ipc::Request::SendFile(self.current_peer.address(), path))
         }
+        let peers = Arc::clone(&self.peers);
+        let peer = self.current_peer.clone();
+        let report = Arc::clone(&self.report);
+        println!("SEND FILE");
+        thread::spawn(move || comms::send_file(peers, peer, path, report));
     }
+
 }
 impl eframe::App for MainWindow {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        match self.listener.try_recv() {
-            Ok(event) => match event {
-                Event::IncomingMsg(mut msg) => {
-                    println!("MESSAGE RECEIVED ON FRONTEND");
-                    let mut retries = 0;
-                    'retry_loop: loop {
-                        for history in self.chat_history.iter_mut() {
-                            if history.peer().ip() == msg.author() {
-                                msg.clean_nulls();
-                                history.push_msg(msg.clone());
-                                println!("MESSAGE PROCESSED");
-                                break 'retry_loop;
-                            }
-                        }
-                        if retries == 2 {break}
-                        let peers = unsafe {KNOWN_PEERS.read().unwrap().clone()};
-                        println!("NO PEER FOUND {}", retries + 1);
-                        msg::try_refresh_history_list(&mut self.chat_history, &peers, true);
-                        retries += 1;
+        if self.locked {
+            egui::CentralPanel::default().show(ctx, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.heading("Whisperer is locked");
+                    ui.label("Your peers and chat histories are encrypted, enter the passphrase to open them:");
+                    let field = ui.add_enabled(!self.thinking, egui::TextEdit::singleline(&mut self.passphrase).password(true));
+                    let entered = field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    if (ui.add_enabled(!self.thinking, egui::Button::new("Unlock")).clicked() || entered) && !self.thinking {
+                        self.unlock(ctx);
                     }
-                }
-                Event::StoreKey(ip, key) => {
-                    println!("STORING KEY");
-                    unsafe {
-                        for peer in KNOWN_PEERS.write().unwrap().iter_mut() {
-                            if peer.ip() == ip {
-                                println!("KEY STORED");
-                                peer.set_private_key(key);
-                                break
-                            }
-                        }
+                    if self.thinking {
+                        ui.spinner();
                     }
-                },
+                    if let Some(e) = &self.passphrase_error {
+                        ui.label(egui::RichText::new(e).color(egui::Color32::RED));
+                    }
+                });
+            });
+            // nothing else runs before the data is unlocked
+            if let Ok(Event::Unlocked(result)) = self.listener.try_recv() {
+                self.thinking = false;
+                match result {
+                    Ok((histories, problems)) => {
+                        for history in histories {
+                            self.peers.replace(history);
+                        }
+                        self.load_problems = problems;
+                        self.locked = false;
+                        self.passphrase.clear();
+                        self.start_network();
+                    },
+                    Err(e) => self.passphrase_error = Some(e.to_string())
+                }
+            }
+            return
+        }
+
+        match self.listener.try_recv() {
+            Ok(event) => match event {
                 Event::NewPeerResult(rec) => {
                     match rec {
-                        Some(rec) => {
+                        Ok(rec) => {
                             println!("NEW PEER INCOMING");
-                            unsafe {KNOWN_PEERS.write().unwrap().push(rec.clone())}
-                            self.chat_history.push(msg::ChatHistory::new(rec.clone()));
+                            self.peers.add(rec.clone());
                             self.new_peer = String::from("SUCCESS");
                             self.current_peer = rec;
                         },
-                        None => self.new_peer = String::from("FAIL: Offline/invalid IP")
+                        Err(e) => self.new_peer = format!("FAIL: {e}")
                     }
                     self.thinking = false;
                 },
-                Event::OverwritePeer(rec) => {
-                    if self.current_peer.ip() == rec.ip() {
-                        self.current_peer = rec.clone();
-                    }
-                    for history in self.chat_history.iter_mut() {
-                        if history.peer().ip() == rec.ip() {
-                            history.update_peer(rec);
-                            println!("PEER OVERWRITTEN");
-                            break
-                        }
+                Event::PassphraseChanged(result) => {
+                    match result {
+                        Ok(()) => {
+                            self.passphrase.clear();
+                            self.new_passphrase.clear();
+                            self.repeat_passphrase.clear();
+                        },
+                        Err(e) => self.passphrase_error = Some(e.to_string())
                     }
+                    self.thinking = false;
                 },
-                Event::SendMessage(success) => {
-                    if !success {
-                        self.sending = false;
-                    } else {
-                        for history in self.chat_history.iter_mut() {
-                            if history.peer() == self.current_peer {
-                                println!("PUSH OWN MESSAGE");
-                                history.push_msg(
-                                    msg::Message::new(
-                                        String::from("You"),
-                                        self.draft.clone()
-                                    )
-                                );
-                                break
+                Event::Unlocked(_) => (),
+                Event::PeerDiscovered(instance) => {
+                    if instance.ip != self.host || instance.port != self.settings.listen_port {
+                        match self.discovered.iter_mut().find(|found| found.ip == instance.ip && found.port == instance.port) {
+                            Some(found) => *found = instance,
+                            None => {
+                                println!("PEER DISCOVERED");
+                                self.discovered.push(instance)
                             }
                         }
-                        
-                        let peer = self.current_peer.clone();
-                        let msg = self.draft.clone();
-                        let callback = self.new_event.clone();
-                        let ctx_update = ctx.clone();
-                        println!("SEND MESSAGE");
-                        thread::spawn(move || comms::send_message(peer, msg, callback, ctx_update));
-                        
-                        self.draft.clear();
-                        self.sending = false;
                     }
                 },
-                Event::ResendLast(ip) => {
-                    let mut last_msg: Option<msg::Message> = None;
-                    for history in self.chat_history.iter_mut() {
-                        if history.peer().ip() == ip {
-                            println!("POP SENT MESSAGE");
-                            last_msg = Some(history.pop_msg());
-                            self.current_peer = history.peer();
-                            break
-                        }
+                Event::Network(comms::Event::FileOffer(offer)) => {
+                    println!("FILE OFFER RECEIVED ON FRONTEND");
+                    self.file_offers.push(offer);
+                },
+                Event::Network(comms::Event::FileProgress(transfer)) => {
+                    self.file_offers.retain(|offer| offer.transfer().id() != transfer.id());
+                    match self.transfers.iter_mut().find(|t| t.id() == transfer.id()) {
+                        Some(existing) => *existing = transfer,
+                        None => self.transfers.push(transfer)
                     }
-                    
-                    if let Some(msg) = last_msg {
-                        println!("RESEND MESSAGE");
-                        self.sending = true;
-                        
-                        self.draft = msg.content();
-                        let ip = self.current_peer.ip();
-                        let sender = self.new_event.clone();
-                        let update_ctx = ctx.clone();
-                        thread::spawn(move || {
-                            match tcp::check_availability(&format!("{ip}:9998")) {
-                                Ok(()) => sender.send(Event::SendMessage(true)).unwrap(),
-                                Err(_) => sender.send(Event::SendMessage(false)).unwrap()
-                            }
-                            update_ctx.request_repaint();
-                        });
+                },
+                Event::Network(comms::Event::IdentityMismatch(address)) => {
+                    println!("IDENTITY MISMATCH");
+                    self.identity_warning = Some(address);
+                },
+                Event::Network(comms::Event::Error(context, e)) => {
+                    println!("ERROR: {context}: {e}");
+                    self.error = Some(format!("{context}: {e}"));
+                },
+                Event::Incoming(address, id) => {
+                    // the chat that is open in front of the user needs no notification
+                    let seen = address == self.current_peer.address() && ctx.input(|i| i.focused);
+                    let notification = notify::message_notification(&self.peers, &address, id);
+                    if let (false, Some(notifier), Some((summary, body))) = (seen, self.notifier.clone(), notification) {
+                        println!("NOTIFY");
+                        thread::spawn(move || notifier.notify(&summary, &body));
                     }
                 },
-                Event::UpdateChatHistory => {
-                    println!("UPDATE CHAT HISTORY");
-                    let peers = unsafe {KNOWN_PEERS.read().unwrap().clone()};
-                    msg::try_refresh_history_list(&mut self.chat_history, &peers, false);
-                }
                 Event::ConfirmationExpired => self.confirm_remove = false
             }
             Err(_) => ()
         }
 
+        // the store holds the only copy of every peer, pick up whatever changed since the last frame
+        self.current_peer = self.peers.get(&self.current_peer.address()).unwrap_or(msg::Recipient::from("None"));
+        let current_unread = self.peers.history(&self.current_peer.address()).map_or(0, |history| history.unread());
+        if current_unread > 0 && ctx.input(|i| i.focused) {
+            self.peers.mark_read(&self.current_peer.address());
+            self.tell_daemon(ipc::Request::MarkRead(self.current_peer.address()));
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             let width = ui.available_width();
             let height = ui.available_height();
@@ -222,20 +450,37 @@ impl eframe::App for MainWindow {
 
             ui.horizontal(|ui| {
                 ui.label("Peer:");
-                ui.add_enabled_ui(!self.sending, |ui| {
-                    egui::ComboBox::from_id_source("choose-peer")
-                        .width(width - 225.0)
-                        .selected_text(egui::RichText::new(self.current_peer.full_string()).monospace())
-                        .show_ui(ui, |ui|
-                    {
-                        for peer in unsafe {KNOWN_PEERS.read().unwrap().iter()} {
-                            ui.selectable_value(
-                                &mut self.current_peer,
-                                peer.clone(),
-                                egui::RichText::new(peer.full_string()).monospace()
-                            );
+                let unread = self.peers.unread();
+                let selected = match unread {
+                    0 => self.current_peer.full_string(),
+                    unread => format!("{} [{unread} new]", self.current_peer.full_string())
+                };
+                egui::ComboBox::from_id_source("choose-peer")
+                    .width(width - 295.0)
+                    .selected_text(egui::RichText::new(selected).monospace())
+                    .show_ui(ui, |ui|
+                {
+                    for history in self.peers.histories().iter() {
+                        let peer = &history.peer();
+                        let name = match history.unread() {
+                            0 => peer.full_string(),
+                            unread => format!("{} [{unread}]", peer.full_string())
+                        };
+                        let text = egui::RichText::new(name).monospace();
+                        ui.selectable_value(
+                            &mut self.current_peer,
+                            peer.clone(),
+                            if history.unread() > 0 { text.strong() } else { text }
+                        );
+                        if let Some(fingerprint) = comms::fingerprint(peer) {
+                            let (mark, col) = match (peer.verified(), peer.key_changed()) {
+                                (true, _) => ("verified", egui::Color32::LIGHT_GREEN),
+                                (false, true) => ("key changed", egui::Color32::RED),
+                                (false, false) => ("unverified", egui::Color32::GRAY)
+                            };
+                            ui.label(egui::RichText::new(format!("{fingerprint} ({mark})")).monospace().small().color(col));
                         }
-                    });
+                    }
                 });
 
                 let (action, s) = match self.current_peer.alias() {
@@ -245,7 +490,7 @@ impl eframe::App for MainWindow {
                 
                 ui.add_enabled_ui(&self.current_peer.ip() != "None", |ui| {
                     ui.menu_button(format!("{s}{action} alias{s}"), |ui| {
-                        let l = self.new_alias.len();
+                        let l = self.new_alias.chars().count();
                         let col = match l {
                             0..=23 => egui::Color32::GRAY,
                             24..=28 => egui::Color32::YELLOW,
@@ -256,32 +501,45 @@ impl eframe::App for MainWindow {
                         ui.horizontal(|ui| {
                             if ui.add_enabled(l > 0 && l <= 28 && &self.new_alias.to_lowercase() != "you", egui::Button::new(format!("{action}"))).clicked() {
                                 self.current_peer.set_alias(Some(self.new_alias.clone()));
-                                unsafe {
-                                    msg::modify_alias(self.current_peer.ip(), Some(self.new_alias.clone()), &mut KNOWN_PEERS.write().unwrap());
-                                }
-                                for history in self.chat_history.iter_mut() {
-                                    if history.peer().ip() == self.current_peer.ip() {
-                                        history.update_peer(self.current_peer.clone());
-                                    }
-                                }
+                                self.peers.modify(&self.current_peer.address(), |peer| peer.set_alias(Some(self.new_alias.clone())));
+                                self.tell_daemon(ipc::Request::Alias(self.current_peer.address(), Some(self.new_alias.clone())));
                                 self.new_alias.clear();
                                 ui.close_menu();
                             }
                             if ui.add_enabled(action == "Change", egui::Button::new("Remove")).clicked() {
                                 self.current_peer.set_alias(None);
-                                unsafe {
-                                    msg::modify_alias(self.current_peer.ip(), None, &mut KNOWN_PEERS.write().unwrap());
-                                }
-                                for history in self.chat_history.iter_mut() {
-                                    if history.peer().ip() == self.current_peer.ip() {
-                                        history.update_peer(self.current_peer.clone());
-                                    }
-                                }
+                                self.peers.modify(&self.current_peer.address(), |peer| peer.set_alias(None));
+                                self.tell_daemon(ipc::Request::Alias(self.current_peer.address(), None));
                                 self.new_alias.clear();
                                 ui.close_menu();
                             }
                             ui.label(egui::RichText::new(format!("{l}/28")).color(col));
                         });
+
+                        ui.separator();
+                        let mut muted = self.current_peer.muted();
+                        if ui.checkbox(&mut muted, "Mute notifications").changed() {
+                            self.current_peer.set_muted(muted);
+                            self.peers.modify(&self.current_peer.address(), |peer| peer.set_muted(muted));
+                            self.tell_daemon(ipc::Request::Muted(self.current_peer.address(), muted));
+                        }
+
+                        if let Some(fingerprint) = comms::fingerprint(&self.current_peer) {
+                            ui.separator();
+                            ui.label("Safety number, compare it with the other computer:");
+                            ui.monospace(fingerprint);
+                            let verified = self.current_peer.verified();
+                            let label = match verified {
+                                true => "Unmark verified",
+                                false => "Mark verified"
+                            };
+                            if ui.button(label).clicked() {
+                                self.current_peer.set_verified(!verified);
+                                self.peers.modify(&self.current_peer.address(), |peer| peer.set_verified(!verified));
+                                self.tell_daemon(ipc::Request::Verified(self.current_peer.address(), !verified));
+                                ui.close_menu();
+                            }
+                        }
                     });
                 });
 
@@ -290,89 +548,68 @@ impl eframe::App for MainWindow {
                         self.new_peer.clear();
                     };
                     ui.horizontal(|ui| {
+                        let address = msg::parse_address(&self.new_peer);
                         #[allow(unused_assignments)]
                         let mut allowed = true;
                         #[allow(unused_assignments)] // it IS being read after being re-assigned :sob:
-                        if &self.new_peer == "127.0.0.1" || &self.new_peer == &self.host { allowed = false }
+                        if let Some((host, port)) = &address {
+                            // another instance on this computer is fine, as long as it listens on another port
+                            if ["127.0.0.1", "::1", "localhost", self.host.as_str()].contains(&host.as_str()) && *port == self.settings.listen_port { allowed = false }
+                        }
                         #[cfg(debug_assertions)] { allowed = true }
-                        if ui.add_enabled(msg::is_valid_ip(&self.new_peer) && !self.thinking && allowed, egui::Button::new(format!("Verify and add"))).clicked() {
-                            self.thinking = true;
-                            let mut alread_exists = false;
-
-                            unsafe {
-                                for peer in KNOWN_PEERS.read().unwrap().iter() {
-                                    if peer.ip() == self.new_peer.clone() {
-                                        alread_exists = true;
-                                        break
-                                    }
-                                }
+                        if ui.add_enabled(address.is_some() && !self.thinking && allowed, egui::Button::new(format!("Verify and add"))).clicked() {
+                            if let Some((host, port)) = address {
+                                self.add_peer(host, port, None, ctx);
                             }
-
-                            if alread_exists {
-                                self.new_peer = String::from("IP already added");
-                                self.thinking = false;
-                            } else {
-                                let ip = self.new_peer.clone();
-                                let sender = self.new_event.clone();
-                                let update_ctx = ctx.clone();
-                                
-                                thread::spawn(move || {
-                                    match tcp::check_availability(&format!("{}:9998", ip.clone())) {
-                                        Ok(_) => {
-                                            match comms::make_keypair(ip.clone()) {
-                                                Ok(key) => {
-                                                    let mut rec = msg::Recipient::from(ip);
-                                                    rec.set_private_key(key);
-                                                    sender.send(Event::NewPeerResult(Some(rec))).unwrap();
-                                                },
-                                                Err(_) => sender.send(Event::NewPeerResult(None)).unwrap()
-                                            }
-                                        },
-                                        Err(_) => sender.send(Event::NewPeerResult(None)).unwrap(),
-                                    }
-                                    update_ctx.request_repaint();
-                                });
-                            }
-
                         }
                         if self.thinking {
                             ui.spinner();
                         }
                     });
+
+                    ui.separator();
+                    let mut enabled = self.discovery.is_some();
+                    if ui.checkbox(&mut enabled, "Discover peers on this network").changed() {
+                        self.set_discovery(enabled, ctx);
+                    }
+                    if let Some(e) = &self.discovery_error {
+                        ui.label(egui::RichText::new(e).color(egui::Color32::RED));
+                    }
+                    if self.discovery.is_some() {
+                        self.discovered.retain(|instance| instance.seen.elapsed() < discovery::FORGET_AFTER);
+                        let known = self.peers.peers();
+                        let mut add: Option<discovery::Instance> = None;
+                        for instance in self.discovered.iter().filter(|instance| !known.iter().any(|peer| peer.ip() == instance.ip && peer.port() == instance.port)) {
+                            if ui.add_enabled(!self.thinking, egui::Button::new(format!("{} ({})", instance.name, instance.ip))).clicked() {
+                                add = Some(instance.clone());
+                            }
+                        }
+                        if let Some(instance) = add {
+                            let alias = Some(instance.name.clone())
+                                .filter(|name| name.chars().count() <= 28 && name.to_lowercase() != "you");
+                            self.add_peer(instance.ip, instance.port, alias, ctx);
+                        }
+                        if self.discovered.is_empty() {
+                            ui.label(egui::RichText::new("Looking for other computers...").weak());
+                        }
+                    }
                 });
 
                 ui.add_enabled_ui(&self.current_peer.ip() != "None", |ui| {
                     ui.menu_button("Remove", |ui| {
                         if ui.button("Delete chat history").clicked() {
-                            for history in self.chat_history.iter_mut() {
-                                if &history.peer() == &self.current_peer {
-                                    history.clear_history();
-                                    break
-                                }
-                            }
+                            comms::OUTBOX.forget(&self.current_peer.address());
+                            self.peers.clear_history(&self.current_peer.address());
+                            self.tell_daemon(ipc::Request::ClearHistory(self.current_peer.address()));
                             ui.close_menu();
                         }
                         match self.confirm_remove {
                             true => if ui.button("Are you sure?").clicked() {
                                 self.confirm_remove = false;
-                                unsafe {
-                                    let peers_rwlock = KNOWN_PEERS.read().unwrap();
-                                    let peers = peers_rwlock.clone();
-                                    drop(peers_rwlock);
-                                    for (i, peer) in peers.iter().enumerate() {
-                                        if peer == &self.current_peer {
-                                            KNOWN_PEERS.write().unwrap().remove(i);
-                                            for (i, history) in self.chat_history.iter().enumerate() {
-                                                if &history.peer() == peer {
-                                                    self.chat_history.remove(i);
-                                                    break
-                                                }
-                                            }
-                                            self.current_peer = peers[0].clone();
-                                            break
-                                        }
-                                    }
-                                }
+                                comms::OUTBOX.forget(&self.current_peer.address());
+                                self.peers.remove(&self.current_peer.address());
+                                self.tell_daemon(ipc::Request::Remove(self.current_peer.address()));
+                                self.current_peer = msg::Recipient::from("None");
                                 ui.close_menu();
                             },
                             false => if ui.button("Actually remove").clicked() {
@@ -381,15 +618,132 @@ impl eframe::App for MainWindow {
                                 let update_ctx = ctx.clone();
                                 thread::spawn(move || {
                                     thread::sleep(std::time::Duration::from_secs(1));
-                                    future_call.send(Event::ConfirmationExpired).unwrap();
+                                    future_call.send(Event::ConfirmationExpired).unwrap_or(());
                                     update_ctx.request_repaint();
                                 });
                             }
                         }
                     });
                 });
+
+                ui.menu_button("History", |ui| {
+                    let formats = [(export::Format::Json, "JSON"), (export::Format::Markdown, "Markdown"), (export::Format::Text, "Text")];
+                    ui.add_enabled_ui(&self.current_peer.ip() != "None", |ui| {
+                        ui.label("Export this chat as:");
+                        ui.horizontal(|ui| {
+                            for (format, name) in formats {
+                                if ui.button(name).clicked() {
+                                    self.export(Some(self.current_peer.address()), format);
+                                }
+                            }
+                        });
+                    });
+                    ui.label("Export every chat as:");
+                    ui.horizontal(|ui| {
+                        for (format, name) in formats {
+                            if ui.button(name).clicked() {
+                                self.export(None, format);
+                            }
+                        }
+                    });
+
+                    ui.separator();
+                    ui.label("Path of a JSON export to import:");
+                    ui.text_edit_singleline(&mut self.import_path);
+                    if ui.add_enabled(!self.import_path.trim().is_empty(), egui::Button::new("Import")).clicked() {
+                        self.import();
+                    }
+                    if let Some(notice) = &self.history_notice {
+                        ui.label(egui::RichText::new(notice).weak());
+                    }
+                });
+
+                ui.menu_button("Settings", |ui| {
+                    ui.label("Port to listen on, takes effect after a restart:");
+                    ui.text_edit_singleline(&mut self.new_port);
+                    let port = self.new_port.trim().parse::<u16>().ok().filter(|port| *port != 0);
+                    ui.horizontal(|ui| {
+                        if ui.add_enabled(port.is_some_and(|port| port != self.settings.listen_port), egui::Button::new("Save")).clicked() {
+                            self.settings.listen_port = port.unwrap_or(tcp::DEFAULT_PORT);
+                            if let Err(e) = save::set_settings(&self.settings) {
+                                self.error = Some(format!("Could not save the settings: {e}"));
+                            }
+                            ui.close_menu();
+                        }
+                        if ui.button("Default").clicked() {
+                            self.new_port = tcp::DEFAULT_PORT.to_string();
+                        }
+                    });
+                    let listening = comms::LISTEN_PORT.load(std::sync::atomic::Ordering::Relaxed);
+                    if self.attached {
+                        ui.label(egui::RichText::new("The daemon listens while it runs, restart it to use a new port.").weak());
+                    } else if listening != self.settings.listen_port {
+                        ui.label(egui::RichText::new(format!("Still listening on {listening} until the app is restarted.")).weak());
+                    }
+
+                    ui.separator();
+                    let encrypted = save::encrypted();
+                    if encrypted {
+                        ui.label("Current passphrase:");
+                        ui.add(egui::TextEdit::singleline(&mut self.passphrase).password(true));
+                    }
+                    ui.label("New passphrase to encrypt your data with:");
+                    ui.add(egui::TextEdit::singleline(&mut self.new_passphrase).password(true));
+                    ui.label("Repeat the new passphrase:");
+                    ui.add(egui::TextEdit::singleline(&mut self.repeat_passphrase).password(true));
+                    let action = match encrypted {
+                        true => "Change",
+                        false => "Set"
+                    };
+                    ui.horizontal(|ui| {
+                        let matching = !self.new_passphrase.is_empty() && self.new_passphrase == self.repeat_passphrase;
+                        if ui.add_enabled(matching && !self.thinking, egui::Button::new(format!("{action} passphrase"))).clicked() {
+                            self.change_passphrase(false, ctx);
+                        }
+                        if ui.add_enabled(encrypted && !self.thinking, egui::Button::new("Remove passphrase")).clicked() {
+                            self.change_passphrase(true, ctx);
+                        }
+                        if self.thinking {
+                            ui.spinner();
+                        }
+                    });
+                    if let Some(e) = &self.passphrase_error {
+                        ui.label(egui::RichText::new(e).color(egui::Color32::RED));
+                    }
+                });
             });
 
+            if let Some(address) = self.identity_warning.clone() {
+                ui.horizontal_wrapped(|ui| {
+                    let peer = match self.peers.alias(&address) {
+                        Some(alias) => format!("{alias} ({address})"),
+                        None => address.clone()
+                    };
+                    ui.label(egui::RichText::new(
+                        format!("WARNING: {peer} presented a different identity than the one pinned for it. Its keys and messages are being refused.")
+                    ).color(egui::Color32::RED));
+                    if ui.button("Trust new identity").clicked() {
+                        println!("IDENTITY FORGOTTEN");
+                        self.peers.modify(&address, |peer| peer.forget_identity());
+                        self.tell_daemon(ipc::Request::ForgetIdentity(address.clone()));
+                        self.identity_warning = None;
+                    }
+                    if ui.button("Dismiss").clicked() {
+                        self.identity_warning = None;
+                    }
+                });
+            }
+
+            if let Some(e) = self.error.clone() {
+                ui.horizontal_wrapped(|ui| {
+                    ui.label(egui::RichText::new(format!("ERROR: {e}")).color(egui::Color32::RED));
+                    if ui.button("Dismiss").clicked() {
+                        self.error = None;
+                    }
+                });
+            }
+
+            let mut retry: Option<msg::Message> = None;
             let mut margin = egui::Margin::default();
             margin.top = 5.0;
             margin.bottom = 5.0;
@@ -409,77 +763,209 @@ impl eframe::App for MainWindow {
                     .stick_to_bottom(true)
                     .show(ui, |ui|
                 {
-                    for history in self.chat_history.iter() {
-                        if history.peer() == self.current_peer {
-                            history.history().iter().for_each(|msg| {
-                                let col = match msg.author().as_str() {
-                                    "You" => egui::Color32::LIGHT_BLUE,
-                                    _ => egui::Color32::LIGHT_RED
-                                };
-                                let author = match msg::find_alias(msg.author(), unsafe {&KNOWN_PEERS.read().unwrap()}) {
-                                    Some(alias) => alias,
-                                    None => msg.author()
-                                };
-        
-                                ui.horizontal_wrapped(|ui| {
-                                    ui.monospace(egui::RichText::new(
-                                        format!("[{}]", author)
-                                    ).color(col));
-                                    ui.monospace(msg.content());
+                    if self.current_peer.key_changed() {
+                        ui.label(egui::RichText::new(
+                            "The identity of this peer changed after you verified it. Compare safety numbers again before trusting it."
+                        ).color(egui::Color32::RED));
+                    }
+                    if let Some(history) = self.peers.history(&self.current_peer.address()) {
+                        let mut last_day: Option<String> = None;
+                        history.history().iter().for_each(|msg| {
+                            let col = match msg.author().as_str() {
+                                "You" => egui::Color32::LIGHT_BLUE,
+                                _ => egui::Color32::LIGHT_RED
+                            };
+                            let author = match (msg.author().as_str(), history.peer().alias()) {
+                                ("You", _) | (_, None) => msg.author(),
+                                (_, Some(alias)) => alias
+                            };
+
+                            let day = msg::format_day(msg.sent_at());
+                            if day.is_some() && day != last_day {
+                                ui.vertical_centered(|ui| {
+                                    ui.label(egui::RichText::new(format!("-- {} --", day.clone().unwrap_or_default())).weak());
                                 });
+                                last_day = day;
+                            }
+    
+                            ui.horizontal_wrapped(|ui| {
+                                if let Some(time) = msg::format_time(msg.sent_at()) {
+                                    let details = format!(
+                                        "Sent {}\nReceived {}",
+                                        msg::format_date_time(msg.sent_at()).unwrap_or_default(),
+                                        msg::format_date_time(msg.received_at()).unwrap_or(String::from("unknown"))
+                                    );
+                                    ui.monospace(egui::RichText::new(time).weak()).on_hover_text(details);
+                                }
+                                ui.monospace(egui::RichText::new(
+                                    format!("[{}]", author)
+                                ).color(col));
+                                ui.monospace(msg.content());
+                                if msg.author() == "You" {
+                                    let (status, col) = match msg.status() {
+                                        msg::MessageStatus::Pending => ("sending", egui::Color32::GRAY),
+                                        msg::MessageStatus::Sent => ("sent", egui::Color32::GRAY),
+                                        msg::MessageStatus::Delivered => ("delivered", egui::Color32::LIGHT_GREEN),
+                                        msg::MessageStatus::Failed => ("failed", egui::Color32::RED),
+                                        msg::MessageStatus::Queued => ("queued", egui::Color32::YELLOW)
+                                    };
+                                    ui.label(egui::RichText::new(status).small().color(col));
+                                    if msg.status() == msg::MessageStatus::Failed && ui.small_button("Retry").clicked() {
+                                        retry = Some(msg.clone());
+                                    }
+                                }
                             });
-                            break
-                        }
+                        });
                     }
                 })
             );
 
-            let l = self.draft.len();
+            if let Some(mut msg) = retry {
+                println!("RETRY MESSAGE");
+                msg.set_status(msg::MessageStatus::Pending);
+                self.peers.set_status(&self.current_peer.address(), msg.id(), msg.status());
+                self.send_message(self.current_peer.clone(), msg);
+            }
+
+            let l = self.draft.chars().count();
             ui.horizontal(|ui| {
-                ui.add_enabled(!self.sending, 
+                ui.add(
                     egui::TextEdit::singleline(&mut self.draft)
-                        .desired_width(width - 102.0)
+                        .desired_width(width - 150.0)
                         .code_editor()
                         .lock_focus(false)
                 );
 
-                ui.add_enabled_ui(l > 0 && l <= 2000 && self.current_peer.ip() != String::from("None") && !self.sending, |ui|
-                    if ui.button("Send Message").clicked() || ui.input(|i| i.key_pressed(egui::Key::Enter) && l > 0 && l <= 2000) {
-                        self.sending = true;
-                        let ip = self.current_peer.ip();
-                        let sender = self.new_event.clone();
-                        let update_ctx = ctx.clone();
-                        thread::spawn(move || {
-                            match tcp::check_availability(&format!("{ip}:9998")) {
-                                Ok(()) => sender.send(Event::SendMessage(true)).unwrap(),
-                                Err(_) => sender.send(Event::SendMessage(false)).unwrap()
-                            }
-                            update_ctx.request_repaint();
-                        });
+                ui.add_enabled_ui(self.current_peer.ip() != "None", |ui| {
+                    ui.menu_button("File", |ui| {
+                        ui.label("Path of the file to send, or drop a file on the window:");
+                        ui.text_edit_singleline(&mut self.file_path);
+                        let path = std::path::PathBuf::from(self.file_path.trim());
+                        if ui.add_enabled(path.is_file(), egui::Button::new("Send file")).clicked() {
+                            self.send_file(path);
+                            self.file_path.clear();
+                            ui.close_menu();
+                        }
+                    });
+                });
+
+                ui.add_enabled_ui(l > 0 && l <= MAX_DRAFT_LENGTH && self.current_peer.ip() != String::from("None"), |ui|
+                    if ui.button("Send Message").clicked() || ui.input(|i| i.key_pressed(egui::Key::Enter) && l > 0 && l <= MAX_DRAFT_LENGTH) {
+                        let mut msg = msg::Message::new(String::from("You"), self.draft.clone());
+                        msg.set_status(msg::MessageStatus::Pending);
+                        println!("PUSH OWN MESSAGE");
+                        self.peers.push_msg(&self.current_peer.address(), msg.clone());
+                        self.send_message(self.current_peer.clone(), msg);
+                        self.draft.clear();
                     }
                 );
             });
 
             let col = match l {
-                0..=1799 => egui::Color32::GRAY,
-                1800..=2000 => egui::Color32::YELLOW,
+                l if l < MAX_DRAFT_LENGTH * 9 / 10 => egui::Color32::GRAY,
+                l if l <= MAX_DRAFT_LENGTH => egui::Color32::YELLOW,
                 _ => egui::Color32::RED,
             };
-            ui.horizontal(|ui| {
-                ui.label(egui::RichText::new(format!("{l}/2000")).color(col));
-                if self.sending {
-                    ui.spinner();
+            ui.label(egui::RichText::new(format!("{l}/{MAX_DRAFT_LENGTH}")).color(col));
+        });
+
+        if self.current_peer.ip() != "None" {
+            let dropped: Vec<std::path::PathBuf> = ctx.input(|i| {
+                i.raw.dropped_files.iter().filter_map(|file| file.path.clone()).collect()
+            });
+            for path in dropped {
+                self.send_file(path);
+            }
+        }
+
+        let mut answered: Vec<u64> = Vec::new();
+        for offer in self.file_offers.iter() {
+            let transfer = offer.transfer();
+            let peer = match self.peers.alias(&transfer.peer()) {
+                Some(alias) => alias,
+                None => transfer.peer()
+            };
+            egui::Window::new("Incoming file")
+                .id(egui::Id::new(("file-offer", transfer.id())))
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui|
+            {
+                ui.label(format!("{peer} wants to send you {} ({}).", transfer.name(), msg::format_size(transfer.size())));
+                ui.horizontal(|ui| {
+                    if ui.button("Accept").clicked() {
+                        offer.answer(true);
+                        answered.push(transfer.id());
+                    }
+                    if ui.button("Decline").clicked() {
+                        offer.answer(false);
+                        answered.push(transfer.id());
+                    }
+                });
+            });
+        }
+        self.file_offers.retain(|offer| !answered.contains(&offer.transfer().id()));
+
+        if !self.load_problems.is_empty() {
+            egui::Window::new("Problems loading your data")
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui|
+            {
+                for problem in self.load_problems.iter() {
+                    ui.label(problem.to_string());
+                }
+                ui.label("Everything else was loaded.");
+                if ui.button("OK").clicked() {
+                    self.load_problems.clear();
                 }
             });
-        });
+        }
+
+        if !self.transfers.is_empty() {
+            egui::Window::new("File transfers")
+                .collapsible(true)
+                .resizable(false)
+                .show(ctx, |ui|
+            {
+                for transfer in self.transfers.iter() {
+                    let peer = match self.peers.alias(&transfer.peer()) {
+                        Some(alias) => alias,
+                        None => transfer.peer()
+                    };
+                    let direction = match transfer.incoming() {
+                        true => format!("{} from {peer}", transfer.name()),
+                        false => format!("{} to {peer}", transfer.name())
+                    };
+                    ui.label(direction);
+                    match transfer.state() {
+                        msg::TransferState::Waiting => { ui.label("Waiting for the other end..."); },
+                        msg::TransferState::Running => {
+                            let fraction = transfer.done() as f32 / transfer.size().max(1) as f32;
+                            ui.add(egui::ProgressBar::new(fraction).text(format!(
+                                "{} / {}", msg::format_size(transfer.done()), msg::format_size(transfer.size())
+                            )));
+                        },
+                        msg::TransferState::Done => { ui.label(egui::RichText::new("Done").color(egui::Color32::LIGHT_GREEN)); },
+                        msg::TransferState::Declined => { ui.label(egui::RichText::new("Declined").color(egui::Color32::YELLOW)); },
+                        msg::TransferState::Failed(e) => { ui.label(egui::RichText::new(format!("Failed: {e}")).color(egui::Color32::RED)); }
+                    }
+                    ui.separator();
+                }
+                if ui.button("Clear finished").clicked() {
+                    self.transfers.retain(|transfer| !transfer.finished());
+                }
+            });
+        }
     }
 
     fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
-        println!("SAVE DATA");
-        let peers = unsafe {KNOWN_PEERS.read().unwrap().clone()};
-        let histories = self.chat_history.clone();
-
-        save::set_data(peers, histories);
+        // every change is in the journal already, this folds it into one file.
+        // The daemon does that itself, and nothing was loaded while locked.
+        if !self.attached && !self.locked {
+            println!("SAVE DATA");
+            save::set_data(&self.peers);
+        }
         println!("CLOSE APP");
     }
 }
@@ -487,7 +973,20 @@ impl eframe::App for MainWindow {
 fn main() {
     let (send, recv) = mpsc::channel::<Event>();
 
-    let host = tcp::get_local_ip();
+    let host = tcp::get_local_ip().unwrap_or(String::from("127.0.0.1"));
+
+    // while the daemon runs, it owns the data and the window only shows it
+    let attached = ipc::running();
+    // sealed data is loaded once the window has asked for its passphrase
+    let locked = !attached && save::locked();
+    let (peers, load_problems) = match attached || locked {
+        true => (Arc::new(peers::PeerStore::default()), Vec::new()),
+        false => {
+            println!("LOAD DATA");
+            let (histories, problems) = save::get_data();
+            (Arc::new(peers::PeerStore::new(histories)), problems)
+        }
+    };
     
     let mut options = eframe::NativeOptions::default();
     options.centered = true;
@@ -511,17 +1010,22 @@ fn main() {
     eframe::run_native(
         "Whisperer", 
         options, 
-        Box::new(|cc| Box::new(MainWindow::new(cc, host, send, recv)))
+        Box::new(move |cc| {
+            let mut window = MainWindow::new(cc, host, peers, attached, locked, send, recv);
+            window.load_problems = load_problems;
+            Box::new(window)
+        })
     ).unwrap_or(());
 }
 
 enum Event {
-    IncomingMsg(msg::Message),
-    StoreKey(String, Vec<u8>),
-    NewPeerResult(Option<msg::Recipient>),
-    OverwritePeer(msg::Recipient),
-    SendMessage(bool),
-    ResendLast(String),
-    UpdateChatHistory,
-    ConfirmationExpired
+    NewPeerResult(Result<msg::Recipient, tcp::Error>),
+    Network(comms::Event),
+    /// A message was added to the history of a peer, by address and ID
+    Incoming(String, u64),
+    PeerDiscovered(discovery::Instance),
+    ConfirmationExpired,
+    /// The data was unlocked and loaded, along with what went wrong while loading it
+    Unlocked(Result<(Vec<msg::ChatHistory>, Vec<save::Problem>), save::Error>),
+    PassphraseChanged(Result<(), save::Error>)
 }
\ No newline at end of file
@@ -0,0 +1,427 @@
+//! Whisperer from the command line, for scripts
+//!
+//! Uses the same data directory as the window, so peers added in one show up in the other.
+//! While the daemon runs, `add`, `send`, `listen` and `import` go through it instead of the network.
+//! Everything meant for scripts goes to stdout, everything else to stderr.
+//! If the data is sealed with a passphrase, it is asked for, or read from `WHISPERER_PASSPHRASE`.
+use std::{env, fs, process::ExitCode, sync::{atomic::Ordering, Arc}, thread, time::Duration};
+use whisperer_core::{comms, export::{self, json_string}, ipc, msg, peers::{Change, PeerStore}, save, tcp};
+
+const USAGE: &str = "\
+Usage: whisperer-cli <command>
+
+Commands:
+    add <address> [alias]     Check that <address> runs Whisperer and agree on a key with it
+    send <peer> <message>     Send <message> to <peer>, by address or alias
+    listen [--json]           Print incoming messages until stopped, one per line
+    peers [--json]            Print every peer
+    daemon                    Receive and save messages in the background, for the window and
+                              the other commands to go through
+    passphrase [--remove]     Set or change the passphrase the data is encrypted with, or remove it
+    export [peer] [--json|--markdown|--text]
+                              Print the chat history with <peer>, or with every peer, as a JSON
+                              archive (the default), a Markdown transcript or plain text
+    import <file>             Bring in the chat histories of a JSON archive, skipping messages
+                              that are already there";
+
+/// Where the passphrase is read from instead of asking for it, for the daemon and scripts
+const PASSPHRASE_VAR: &str = "WHISPERER_PASSPHRASE";
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Add {address: String, alias: Option<String>},
+    Send {peer: String, message: String},
+    Listen {json: bool},
+    Peers {json: bool},
+    Daemon,
+    Passphrase {remove: bool},
+    Export {peer: Option<String>, format: export::Format},
+    Import {file: String}
+}
+
+/// Read the arguments after the program name, `None` if they are not a valid command
+fn parse_args(args: &[String]) -> Option<Command> {
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+    match args.as_slice() {
+        ["add", address] => Some(Command::Add {address: address.to_string(), alias: None}),
+        ["add", address, alias] => Some(Command::Add {address: address.to_string(), alias: Some(alias.to_string())}),
+        ["send", peer, message @ ..] if !message.is_empty() => Some(Command::Send {peer: peer.to_string(), message: message.join(" ")}),
+        ["listen"] => Some(Command::Listen {json: false}),
+        ["listen", "--json"] => Some(Command::Listen {json: true}),
+        ["peers"] => Some(Command::Peers {json: false}),
+        ["peers", "--json"] => Some(Command::Peers {json: true}),
+        ["daemon"] => Some(Command::Daemon),
+        ["passphrase"] => Some(Command::Passphrase {remove: false}),
+        ["passphrase", "--remove"] => Some(Command::Passphrase {remove: true}),
+        ["export"] => Some(Command::Export {peer: None, format: export::Format::Json}),
+        ["export", flag] if flag.starts_with("--") => Some(Command::Export {peer: None, format: export_format(flag)?}),
+        ["export", peer] => Some(Command::Export {peer: Some(peer.to_string()), format: export::Format::Json}),
+        ["export", peer, flag] => Some(Command::Export {peer: Some(peer.to_string()), format: export_format(flag)?}),
+        ["import", file] => Some(Command::Import {file: file.to_string()}),
+        _ => None
+    }
+}
+
+fn export_format(flag: &str) -> Option<export::Format> {
+    match flag {
+        "--json" => Some(export::Format::Json),
+        "--markdown" => Some(export::Format::Markdown),
+        "--text" => Some(export::Format::Text),
+        _ => None
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some(command) = parse_args(&args) else {
+        eprintln!("{USAGE}");
+        return ExitCode::from(2)
+    };
+
+    let settings = save::get_settings();
+    comms::LISTEN_PORT.store(settings.listen_port, Ordering::Relaxed);
+    let daemon = ipc::running();
+    if let Command::Passphrase {remove} = command {
+        return passphrase(remove, daemon)
+    }
+    if !unlock() {
+        return ExitCode::FAILURE
+    }
+    let (histories, problems) = save::get_data();
+    for problem in problems {
+        eprintln!("warning: {problem}");
+    }
+    let peers = Arc::new(PeerStore::new(histories));
+    // while the daemon runs, it saves what changes
+    if !daemon {
+        save::keep_saved(&peers);
+    }
+
+    match command {
+        Command::Add {address, alias} => add(&peers, &address, alias, daemon),
+        Command::Send {peer, message} => send(&peers, &peer, message, daemon),
+        Command::Listen {json} if daemon => listen_attached(json),
+        Command::Listen {json} => listen(peers, settings.listen_port, json),
+        Command::Peers {json} => {
+            for peer in peers.peers() {
+                println!("{}", peer_line(&peer, json));
+            }
+            ExitCode::SUCCESS
+        },
+        Command::Daemon if daemon => {
+            eprintln!("error: the daemon is already running");
+            ExitCode::FAILURE
+        },
+        Command::Daemon => {
+            // only returns if nothing could listen on the port
+            ipc::daemon::run(settings.listen_port, Arc::new(report));
+            ExitCode::FAILURE
+        },
+        Command::Export {peer, format} => export(&peers, peer.as_deref(), format),
+        Command::Import {file} => import(&peers, &file, daemon),
+        Command::Passphrase {..} => unreachable!()
+    }
+}
+
+/// Give the passphrase the data is sealed with, if it is. Returns whether the data can be loaded.
+fn unlock() -> bool {
+    if !save::locked() {
+        return true
+    }
+    let Some(passphrase) = env::var(PASSPHRASE_VAR).ok().or_else(|| read_passphrase("Passphrase: ")) else {
+        return false
+    };
+    match save::unlock(&passphrase) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("error: could not unlock the data: {e}");
+            false
+        }
+    }
+}
+
+/// Set, change or remove the passphrase, through the daemon if it runs
+fn passphrase(remove: bool, daemon: bool) -> ExitCode {
+    let current = match save::encrypted() {
+        true => match read_passphrase("Current passphrase: ") {
+            Some(current) => Some(current),
+            None => return ExitCode::FAILURE
+        },
+        false if remove => {
+            eprintln!("error: the data has no passphrase");
+            return ExitCode::FAILURE
+        },
+        false => None
+    };
+    let new = match remove {
+        true => None,
+        false => {
+            let (Some(new), Some(again)) = (read_passphrase("New passphrase: "), read_passphrase("Repeat the new passphrase: ")) else {
+                return ExitCode::FAILURE
+            };
+            if new.is_empty() || new != again {
+                eprintln!("error: the passphrases are empty or do not match");
+                return ExitCode::FAILURE
+            }
+            Some(new)
+        }
+    };
+
+    let changed = match daemon {
+        true => ipc::request(&ipc::Request::Passphrase {current, new}).map(|_| ()).map_err(|e| e.to_string()),
+        false => save::set_passphrase(current.as_deref(), new.as_deref()).map_err(|e| e.to_string())
+    };
+    match changed {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: could not change the passphrase: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Ask for a passphrase on the terminal without showing it
+fn read_passphrase(prompt: &str) -> Option<String> {
+    match rpassword::prompt_password(prompt) {
+        Ok(passphrase) => Some(passphrase),
+        Err(e) => {
+            eprintln!("error: could not read the passphrase: {e}");
+            None
+        }
+    }
+}
+
+fn add(peers: &PeerStore, address: &str, alias: Option<String>, daemon: bool) -> ExitCode {
+    let Some((host, port)) = msg::parse_address(address) else {
+        eprintln!("error: {address} is not a valid address");
+        return ExitCode::FAILURE
+    };
+    if peers.contains(&msg::address(&host, port)) {
+        eprintln!("error: {address} is already added");
+        return ExitCode::FAILURE
+    }
+
+    let added = match daemon {
+        true => ipc::add_peer(host, port, alias),
+        false => tcp::check_availability((host.as_str(), port))
+            .and_then(|_| comms::make_keypair(host.clone(), port, None))
+            .map(|(identity, key)| {
+                let mut peer = msg::Recipient::from(host);
+                peer.set_port(port);
+                peer.set_alias(alias);
+                peer.set_identity(Some(identity));
+                peer.set_private_key(key);
+                peers.add(peer.clone());
+                save::set_data(peers);
+                peer
+            })
+    };
+    match added {
+        Ok(peer) => {
+            println!("{}", peer_line(&peer, false));
+            ExitCode::SUCCESS
+        },
+        Err(e) => {
+            eprintln!("error: could not add {address}: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Print the chat history with the peer called `name`, or with every peer, in `format`
+fn export(peers: &PeerStore, name: Option<&str>, format: export::Format) -> ExitCode {
+    let histories = match name {
+        Some(name) => match find_peer(peers, name).and_then(|peer| peers.history(&peer.address())) {
+            Some(history) => vec![history],
+            None => {
+                eprintln!("error: there is no peer called {name}");
+                return ExitCode::FAILURE
+            }
+        },
+        None => peers.histories()
+    };
+    print!("{}", export::export(&histories, format));
+    ExitCode::SUCCESS
+}
+
+/// Bring in the chat histories of the archive at `file`, and print how many messages were added
+fn import(peers: &PeerStore, file: &str, daemon: bool) -> ExitCode {
+    let histories = match fs::read_to_string(file) {
+        Ok(text) => export::from_json(&text).map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string())
+    };
+    let histories = match histories {
+        Ok(histories) => histories,
+        Err(e) => {
+            eprintln!("error: could not read {file}: {e}");
+            return ExitCode::FAILURE
+        }
+    };
+
+    let added = match daemon {
+        true => ipc::import(histories),
+        false => Ok(histories.into_iter().map(|history| peers.merge(history)).sum())
+    };
+    match added {
+        Ok(added) => {
+            println!("{added} messages imported");
+            ExitCode::SUCCESS
+        },
+        Err(e) => {
+            eprintln!("error: could not import {file}: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Send `message` and print how far it got. Messages to offline peers are queued, and sent by
+/// the daemon, the window or `listen` once the peer is back.
+fn send(peers: &Arc<PeerStore>, name: &str, message: String, daemon: bool) -> ExitCode {
+    let Some(peer) = find_peer(peers, name) else {
+        eprintln!("error: there is no peer called {name}");
+        return ExitCode::FAILURE
+    };
+
+    let mut msg = msg::Message::new(String::from("You"), message);
+    msg.set_status(msg::MessageStatus::Pending);
+    let status = match daemon {
+        true => match ipc::send_message(peer.address(), msg) {
+            Ok(status) => status,
+            Err(e) => {
+                eprintln!("error: could not send to {name}: {e}");
+                return ExitCode::FAILURE
+            }
+        },
+        false => {
+            peers.push_msg(&peer.address(), msg.clone());
+            comms::send_message(Arc::clone(peers), peer.clone(), msg.clone(), Arc::new(report));
+            save::set_data(peers);
+            peers.find_msg(&peer.address(), msg.id()).map(|msg| msg.status()).unwrap_or_default()
+        }
+    };
+    println!("{}", status.name());
+    match status {
+        msg::MessageStatus::Failed => ExitCode::FAILURE,
+        _ => ExitCode::SUCCESS
+    }
+}
+
+/// Receive messages and print them until the process is stopped
+fn listen(peers: Arc<PeerStore>, port: u16, json: bool) -> ExitCode {
+    for history in peers.histories().iter() {
+        for msg in history.history().iter() {
+            if msg.author() == "You" && msg.status() == msg::MessageStatus::Queued {
+                comms::OUTBOX.queue(history.peer().address(), msg.clone());
+            }
+        }
+    }
+
+    let store = Arc::clone(&peers);
+    peers.watch(move |change| {
+        if let Change::Message(address, id) = &change {
+            if let Some(msg) = store.find_msg(address, *id).filter(|msg| msg.author() != "You") {
+                println!("{}", message_line(&store, address, &msg, json));
+            }
+        }
+    });
+
+    let outbox_peers = Arc::clone(&peers);
+    thread::spawn(move || comms::outbox_thread(Arc::new(report), outbox_peers));
+    // only returns if nothing could listen on the port
+    comms::request_handler_thread(Arc::new(report), port, peers);
+    ExitCode::FAILURE
+}
+
+/// Print the messages the daemon receives until the process is stopped or the daemon stops.
+/// Files are left for the window to answer.
+fn listen_attached(json: bool) -> ExitCode {
+    let mirror = Arc::new(PeerStore::default());
+    let store = Arc::clone(&mirror);
+    mirror.watch(move |change| {
+        if let Change::Message(address, id) = &change {
+            if let Some(msg) = store.find_msg(address, *id).filter(|msg| msg.author() != "You") {
+                println!("{}", message_line(&store, address, &msg, json));
+            }
+        }
+    });
+
+    let attached = ipc::attach(mirror, Arc::new(|event| match event {
+        comms::Event::FileOffer(_) => (),
+        event => report(event)
+    }));
+    if let Err(e) = attached {
+        eprintln!("error: could not attach to the daemon: {e}");
+        return ExitCode::FAILURE
+    }
+    while ipc::running() {
+        thread::sleep(Duration::from_secs(5));
+    }
+    eprintln!("error: the daemon stopped");
+    ExitCode::FAILURE
+}
+
+/// The peer with the address or alias `name`. An address without a port is the peer on the
+/// default port, or else the first one on that host.
+fn find_peer(peers: &PeerStore, name: &str) -> Option<msg::Recipient> {
+    let by_address = msg::parse_address(name).and_then(|(host, port)| {
+        let on_host = || peers.on_host(&host).into_iter().next().filter(|_| name == host);
+        peers.get(&msg::address(&host, port)).or_else(on_host)
+    });
+    by_address.or_else(|| peers.peers().into_iter().find(|peer| peer.alias().as_deref() == Some(name)))
+}
+
+/// Tell the user what the network threads report. There is nobody to ask about files, so they are declined.
+fn report(event: comms::Event) {
+    match event {
+        comms::Event::IdentityMismatch(address) => eprintln!("warning: {address} presented a different identity than the one pinned for it"),
+        comms::Event::FileOffer(offer) => {
+            eprintln!("declined file {} from {}", offer.transfer().name(), offer.transfer().peer());
+            offer.answer(false);
+        },
+        comms::Event::FileProgress(_) => (),
+        comms::Event::Error(context, e) => eprintln!("error: {context}: {e}")
+    }
+}
+
+fn peer_line(peer: &msg::Recipient, json: bool) -> String {
+    let fingerprint = comms::fingerprint(peer);
+    if json {
+        return format!(
+            "{{\"address\":{},\"alias\":{},\"safety_number\":{},\"verified\":{}}}",
+            json_string(&peer.address()),
+            peer.alias().map_or(String::from("null"), |alias| json_string(&alias)),
+            fingerprint.map_or(String::from("null"), |fingerprint| json_string(&fingerprint)),
+            peer.verified()
+        )
+    }
+
+    let verified = match peer.verified() {
+        true => "verified",
+        false => "unverified"
+    };
+    match fingerprint {
+        Some(fingerprint) => format!("{}\t{fingerprint} ({verified})", peer.full_string()),
+        None => peer.full_string()
+    }
+}
+
+fn message_line(peers: &PeerStore, address: &str, msg: &msg::Message, json: bool) -> String {
+    let alias = peers.alias(address);
+    if json {
+        return format!(
+            "{{\"from\":{},\"alias\":{},\"id\":{},\"sent_at\":{},\"received_at\":{},\"content\":{}}}",
+            json_string(&msg.author()),
+            alias.map_or(String::from("null"), |alias| json_string(&alias)),
+            msg.id(),
+            msg.sent_at(),
+            msg.received_at(),
+            json_string(&msg.content())
+        )
+    }
+
+    let time = msg::format_date_time(msg.sent_at()).unwrap_or_default();
+    format!("{time} [{}] {}", alias.unwrap_or(msg.author()), msg.content())
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,67 @@
+#![cfg(test)]
+
+use super::*;
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn commands_are_parsed() {
+    assert_eq!(parse_args(&args(&["add", "10.0.0.2"])), Some(Command::Add {address: String::from("10.0.0.2"), alias: None}));
+    assert_eq!(parse_args(&args(&["add", "laptop.local:9999", "laptop"])), Some(Command::Add {
+        address: String::from("laptop.local:9999"),
+        alias: Some(String::from("laptop"))
+    }));
+    assert_eq!(parse_args(&args(&["send", "laptop", "build", "done"])), Some(Command::Send {
+        peer: String::from("laptop"),
+        message: String::from("build done")
+    }));
+    assert_eq!(parse_args(&args(&["listen", "--json"])), Some(Command::Listen {json: true}));
+    assert_eq!(parse_args(&args(&["peers"])), Some(Command::Peers {json: false}));
+    assert_eq!(parse_args(&args(&["daemon"])), Some(Command::Daemon));
+    assert_eq!(parse_args(&args(&["passphrase", "--remove"])), Some(Command::Passphrase {remove: true}));
+
+    assert_eq!(parse_args(&args(&["export"])), Some(Command::Export {peer: None, format: export::Format::Json}));
+    assert_eq!(parse_args(&args(&["export", "--markdown"])), Some(Command::Export {peer: None, format: export::Format::Markdown}));
+    assert_eq!(parse_args(&args(&["export", "laptop", "--text"])), Some(Command::Export {
+        peer: Some(String::from("laptop")),
+        format: export::Format::Text
+    }));
+    assert_eq!(parse_args(&args(&["import", "chats.json"])), Some(Command::Import {file: String::from("chats.json")}));
+
+    assert_eq!(parse_args(&args(&[])), None);
+    assert_eq!(parse_args(&args(&["export", "--yaml"])), None);
+    assert_eq!(parse_args(&args(&["export", "laptop", "desk"])), None);
+    assert_eq!(parse_args(&args(&["send", "laptop"])), None);
+    assert_eq!(parse_args(&args(&["listen", "--yaml"])), None);
+}
+
+#[test]
+fn json_strings_are_escaped() {
+    assert_eq!(json_string("plain"), "\"plain\"");
+    assert_eq!(json_string("say \"hi\"\n\\o/"), "\"say \\\"hi\\\"\\n\\\\o/\"");
+    assert_eq!(json_string("bell\u{7}"), "\"bell\\u0007\"");
+    assert_eq!(json_string("üñí"), "\"üñí\"");
+}
+
+#[test]
+fn peers_are_found_by_address_or_alias() {
+    let peers = PeerStore::default();
+    let mut peer = msg::Recipient::from("fe80::1%eth0");
+    peer.set_port(9999);
+    peer.set_alias(Some(String::from("desk")));
+    peers.add(peer);
+
+    assert!(find_peer(&peers, "fe80::1%eth0").is_some());
+    assert!(find_peer(&peers, "[fe80::1%eth0]:9999").is_some());
+    assert!(find_peer(&peers, "[fe80::1%eth0]:9998").is_none());
+    assert!(find_peer(&peers, "desk").is_some());
+    assert!(find_peer(&peers, "laptop").is_none());
+
+    let mut other = msg::Recipient::from("fe80::1%eth0");
+    other.set_port(9000);
+    peers.add(other);
+    assert_eq!(find_peer(&peers, "[fe80::1%eth0]:9000").map(|peer| peer.port()), Some(9000));
+    assert_eq!(find_peer(&peers, "[fe80::1%eth0]:9999").map(|peer| peer.port()), Some(9999));
+}
@@ -0,0 +1,577 @@
+use crate::{
+    msg, kem, save,
+    peers::PeerStore,
+    tcp::{
+        self,
+        file,
+        StreamReader
+    }
+};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, ToSocketAddrs},
+    path::PathBuf,
+    sync::{atomic::{AtomicU16, Ordering}, mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, Instant}
+};
+use once_cell::sync::Lazy;
+
+/// This install's long-term identity, loaded from disk the first time it is used
+pub static IDENTITY: Lazy<kem::Identity> = Lazy::new(save::get_identity);
+
+/// The port this install listens on, set once the request handler is listening
+pub static LISTEN_PORT: AtomicU16 = AtomicU16::new(tcp::DEFAULT_PORT);
+
+/// Something the network threads want the user to know about
+pub enum Event {
+    /// A peer presented a different identity than the one pinned for it, by address
+    IdentityMismatch(String),
+    /// Another computer wants to send a file
+    FileOffer(FileOffer),
+    /// A file transfer started, progressed or ended
+    FileProgress(msg::Transfer),
+    /// Something failed, with what was being done when it did
+    Error(String, tcp::Error)
+}
+
+/// Receives the events of the network threads, on whichever thread they happen
+pub type Reporter = Arc<dyn Fn(Event) + Send + Sync>;
+
+/// Messages waiting for their peer to come back online
+pub static OUTBOX: Lazy<Outbox> = Lazy::new(Outbox::default);
+
+/// How long the outbox waits before trying an offline peer for the first time
+const FIRST_BACKOFF: Duration = Duration::from_secs(5);
+/// The longest the outbox waits between two tries
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Messages that could not be sent because their peer was offline, by peer address.
+/// Every peer is tried again after a delay that doubles up to `MAX_BACKOFF` each time it is still offline.
+#[derive(Default)]
+pub struct Outbox {
+    queued: Mutex<Vec<(String, msg::Message)>>,
+    backoff: Mutex<HashMap<String, (Instant, Duration)>>
+}
+impl Outbox {
+    /// Queue `msg` for the peer with `address`, behind everything already queued for it
+    pub fn queue(&self, address: String, msg: msg::Message) {
+        let mut queued = self.queued.lock().unwrap();
+        if queued.iter().any(|(_, queued)| queued.id() == msg.id()) {return}
+        queued.push((address.clone(), msg));
+        drop(queued);
+
+        self.backoff.lock().unwrap().entry(address).or_insert((Instant::now() + FIRST_BACKOFF, FIRST_BACKOFF));
+    }
+    /// Whether anything is queued for the peer with `address`
+    pub fn has_queued(&self, address: &str) -> bool {
+        self.queued.lock().unwrap().iter().any(|(queued, _)| queued == address)
+    }
+    /// Drop everything queued for the peer with `address`
+    pub fn forget(&self, address: &str) {
+        self.queued.lock().unwrap().retain(|(queued, _)| queued != address);
+        self.backoff.lock().unwrap().remove(address);
+    }
+    /// The peers that are due to be tried again
+    fn due(&self) -> Vec<String> {
+        let now = Instant::now();
+        self.backoff.lock().unwrap().iter()
+            .filter(|(_, (next, _))| *next <= now)
+            .map(|(address, _)| address.clone())
+            .collect()
+    }
+    /// Wait longer before trying the peer with `address` again
+    fn back_off(&self, address: &str) {
+        if let Some((next, delay)) = self.backoff.lock().unwrap().get_mut(address) {
+            *delay = (*delay * 2).min(MAX_BACKOFF);
+            *next = Instant::now() + *delay;
+        }
+    }
+    /// Take everything queued for the peer with `address` out of the outbox, oldest first
+    fn take(&self, address: &str) -> Vec<msg::Message> {
+        self.backoff.lock().unwrap().remove(address);
+        let mut queued = self.queued.lock().unwrap();
+        let (taken, kept): (Vec<_>, Vec<_>) = queued.drain(..).partition(|(queued, _)| queued == address);
+        *queued = kept;
+        taken.into_iter().map(|(_, msg)| msg).collect()
+    }
+}
+
+/// Send the messages in `OUTBOX` once their peers answer again
+pub fn outbox_thread(report: Reporter, peers: Arc<PeerStore>) {
+    loop {
+        thread::sleep(Duration::from_secs(1));
+
+        for address in OUTBOX.due() {
+            let Some(peer) = peers.get(&address) else {
+                eprintln!("PEER REMOVED, DROPPING QUEUED MESSAGES");
+                OUTBOX.forget(&address);
+                continue
+            };
+            if tcp::check_availability((peer.ip().as_str(), peer.port())).is_err() {
+                eprintln!("PEER STILL OFFLINE");
+                OUTBOX.back_off(&address);
+                continue
+            }
+
+            eprintln!("PEER BACK ONLINE, FLUSHING OUTBOX");
+            for msg in OUTBOX.take(&address) {
+                send_message(Arc::clone(&peers), peer.clone(), msg, Arc::clone(&report));
+            }
+        }
+    }
+}
+
+/// A file another computer wants to send, waiting for the user to accept or decline it
+pub struct FileOffer {
+    transfer: msg::Transfer,
+    answer: mpsc::Sender<bool>
+}
+impl FileOffer {
+    /// An offer of `transfer`, the answer arrives on the returned receiver
+    pub fn new(transfer: msg::Transfer) -> (Self, mpsc::Receiver<bool>) {
+        let (answer, decision) = mpsc::channel::<bool>();
+        (Self {transfer, answer}, decision)
+    }
+    pub fn transfer(&self) -> msg::Transfer {
+        self.transfer.clone()
+    }
+    pub fn answer(&self, accept: bool) {
+        self.answer.send(accept).unwrap_or(());
+    }
+}
+
+/// Listen for other computers on `port` and handle what they send
+pub fn request_handler_thread(report: Reporter, port: u16, peers: Arc<PeerStore>) {
+    let listeners = match listen(port) {
+        Ok(listeners) => listeners,
+        Err(e) => {
+            eprintln!("COULD NOT LISTEN: {e}");
+            report(Event::Error(String::from("Nobody can send you anything"), tcp::Error::Listen(port, e)));
+            return
+        }
+    };
+    LISTEN_PORT.store(port, Ordering::Relaxed);
+
+    let base_key = Arc::new(kem::generate_secret());
+
+    let handlers: Vec<thread::JoinHandle<()>> = listeners.into_iter().map(|listener| {
+        let base_key = Arc::clone(&base_key);
+        let peers = Arc::clone(&peers);
+        let report = Arc::clone(&report);
+        thread::spawn(move || handle_requests(listener, base_key, peers, report))
+    }).collect();
+    for handler in handlers {
+        handler.join().unwrap_or(());
+    }
+}
+
+/// Listen on `port` on every IPv6 and IPv4 address. Where the IPv6 socket takes IPv4 connections
+/// as well, the IPv4 socket cannot be bound next to it and is left out.
+fn listen(port: u16) -> Result<Vec<TcpListener>, std::io::Error> {
+    let mut listeners: Vec<TcpListener> = Vec::new();
+    let mut error: Option<std::io::Error> = None;
+    for addr in [SocketAddr::from((Ipv6Addr::UNSPECIFIED, port)), SocketAddr::from((Ipv4Addr::UNSPECIFIED, port))] {
+        match TcpListener::bind(addr) {
+            Ok(listener) => listeners.push(listener),
+            Err(e) => error = Some(e)
+        }
+    }
+
+    match error {
+        Some(e) if listeners.is_empty() => Err(e),
+        _ => Ok(listeners)
+    }
+}
+
+fn handle_requests(listener: TcpListener, base_key: Arc<kem::Secret>, peers: Arc<PeerStore>, report: Reporter) {
+    for req in listener.incoming() {
+        let mut stream = match req {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("COULD NOT ACCEPT CONNECTION: {e}");
+                continue
+            }
+        };
+
+        let base_key = Arc::clone(&base_key);
+        let peers = Arc::clone(&peers);
+        let report = Arc::clone(&report);
+        thread::spawn(move || {
+            let from = stream.peer_addr()
+                .map(|addr| addr.ip().to_canonical().to_string())
+                .unwrap_or(String::from("unknown address"));
+
+            let result = stream.parse_incoming(|stream, protocol, data| match protocol {
+                tcp::Protocol::PublicKey => {
+                    eprintln!("SHIP PUBLIC KEY");
+                    match kem::verify_key(&data, &[]) {
+                        Ok((_, their_key)) => {
+                            let public_key = kem::public_key(&base_key);
+                            let bundle = kem::sign_key(&IDENTITY, &public_key, &their_key);
+                            stream.respond(&bundle)
+                        },
+                        Err(e) => {
+                            eprintln!("INVALID PUBLIC KEY");
+                            stream.respond(&[])?;
+                            Err(e.into())
+                        }
+                    }
+                },
+                tcp::Protocol::CombineKey => {
+                    eprintln!("GENERATE PRIVATE KEY FROM PUBLIC KEY");
+                    let from = stream.peer_addr()?.ip();
+
+                    let public_key = kem::public_key(&base_key);
+                    let (bundle, port) = data.split_at(data.len().min(kem::BUNDLE_SIZE));
+                    let agreed = kem::verify_key(bundle, &[public_key.as_slice(), port].concat()).and_then(|(identity, their_key)| {
+                        let port = u16::from_be_bytes(port.try_into().map_err(|_| kem::Error)?);
+                        Ok((identity, port, kem::agree(&base_key, &their_key, &their_key, &public_key)?))
+                    });
+                    let (identity, port, private_key) = match agreed {
+                        Ok(agreed) => agreed,
+                        Err(e) => {
+                            eprintln!("INVALID PUBLIC KEY");
+                            stream.respond(&[1u8])?;
+                            return Err(e.into())
+                        }
+                    };
+
+                    // the port it listens on tells instances on one host apart, so it is named the way it was added
+                    let at_host = peers_at(&peers, from);
+                    let host = at_host.iter().find(|peer| peer.port() == port)
+                        .or_else(|| at_host.iter().find(|peer| peer.identity().as_ref() == Some(&identity)))
+                        .or(at_host.first())
+                        .map_or(from.to_canonical().to_string(), |peer| peer.ip());
+                    match peers.store_key(&host, port, identity, private_key) {
+                        Ok(()) => {
+                            eprintln!("PRIVATE KEY STORED");
+                            stream.respond(&[0u8])
+                        },
+                        Err(_) => {
+                            eprintln!("IDENTITY MISMATCH, REFUSING KEY");
+                            identity_mismatch(&peers, &report, msg::address(&host, port));
+                            stream.respond(&[3u8])
+                        }
+                    }
+                },
+                tcp::Protocol::Message => {
+                    eprintln!("MESSAGE RECEIVED ON BACKEND");
+                    let from = stream.peer_addr()?.ip();
+                    let at_host = peers_at(&peers, from);
+
+                    let Some((id, data)) = tcp::split_message(&data) else {
+                        stream.respond(&[1u8])?;
+                        return Err(tcp::Error::Protocol("Message is too short to carry an ID"))
+                    };
+
+                    // a host can run more than one instance, the author is the one whose key the message is sealed with
+                    let opened = at_host.iter().find_map(|peer| {
+                        let message = kem::decrypt(data.clone(), peer.private_key()?).ok()?;
+                        let message = msg::Message::from_bytes(peer.ip(), &message).filter(|message| message.id() == id)?;
+                        Some((peer.address(), message))
+                    });
+                    if let Some((address, mut message)) = opened {
+                        stream.respond(&tcp::delivery_receipt(id))?;
+                        message.clean_nulls();
+                        peers.push_msg(&address, message);
+                        return Ok(())
+                    }
+
+                    let keyless = match at_host.iter().find(|peer| peer.private_key().is_none()) {
+                        Some(peer) => Some(peer.clone()),
+                        None if at_host.is_empty() => Some(msg::Recipient::from(from.to_canonical().to_string())),
+                        None => None
+                    };
+                    match keyless {
+                        Some(peer) => {
+                            let (author, author_port) = (peer.ip(), peer.port());
+                            let address = peer.address();
+                            eprintln!("NO KEY FOUND, REBUILDING");
+                            let stored = make_keypair(author.clone(), author_port, peer.identity())
+                                .and_then(|(identity, key)| peers.store_key(&author, author_port, identity, key));
+                            match stored {
+                                Ok(()) => (),
+                                Err(tcp::Error::IdentityMismatch) => {
+                                    eprintln!("IDENTITY MISMATCH, REFUSING MESSAGE");
+                                    identity_mismatch(&peers, &report, address);
+                                    return stream.respond(&[3u8])
+                                },
+                                Err(e) => {
+                                    eprintln!("COULD NOT REBUILD KEY");
+                                    stream.respond(&[1u8])?;
+                                    return Err(e)
+                                }
+                            }
+
+                            stream.respond(&[0u8])?;
+                            eprintln!("REQUEST RESEND");
+                            tcp::request_resend((author.as_str(), author_port), id)
+                        },
+                        None => {
+                            eprintln!("MESSAGE FAILED AUTHENTICATION");
+                            stream.respond(&[2u8])?;
+                            Err(tcp::Error::Authentication)
+                        }
+                    }
+                },
+                tcp::Protocol::File => {
+                    eprintln!("FILE OFFERED");
+                    let at_host = peers_at(&peers, stream.peer_addr()?.ip());
+
+                    let opened = at_host.into_iter().find_map(|peer| {
+                        let key = peer.private_key()?;
+                        let offer = kem::decrypt(data.clone(), key.clone()).ok()?;
+                        Some((peer, key, file::Offer::from_bytes(&offer)?))
+                    });
+                    let Some((peer, key, offer)) = opened else {
+                        eprintln!("FILE OFFER FAILED AUTHENTICATION");
+                        stream.respond(&[2u8])?;
+                        return Err(tcp::Error::Authentication)
+                    };
+
+                    let mut transfer = msg::Transfer::new(peer.address(), offer.name.clone(), offer.size, true);
+                    let (offer_event, decision) = FileOffer::new(transfer.clone());
+                    report(Event::FileOffer(offer_event));
+
+                    if decision.recv_timeout(file::OFFER_TIMEOUT) != Ok(true) {
+                        eprintln!("FILE DECLINED");
+                        transfer.set_state(msg::TransferState::Declined);
+                        report(Event::FileProgress(transfer));
+                        return stream.respond(&[4u8])
+                    }
+
+                    let dest = match save::download_path(&offer.name) {
+                        Ok(dest) => dest,
+                        Err(e) => {
+                            eprintln!("COULD NOT PICK A DOWNLOAD PATH: {e}");
+                            stream.respond(&[1u8])?;
+                            transfer.set_state(msg::TransferState::Failed(e.to_string()));
+                            report(Event::FileProgress(transfer));
+                            report(Event::Error(format!("Could not store {}", offer.name), e.into()));
+                            return Ok(())
+                        }
+                    };
+                    eprintln!("RECEIVING FILE");
+                    transfer.set_state(msg::TransferState::Running);
+                    let mut running = transfer.clone();
+                    let result = file::receive_file(stream, &offer, key, &dest, |done| {
+                        running.set_done(done);
+                        report(Event::FileProgress(running.clone()));
+                    });
+
+                    // a failed transfer is shown in the transfer window, not as an error
+                    transfer.set_done(offer.size);
+                    match result {
+                        Ok(()) => {
+                            eprintln!("FILE RECEIVED");
+                            transfer.set_path(dest);
+                            transfer.set_state(msg::TransferState::Done);
+                            record_transfer(&peers, &transfer);
+                        },
+                        Err(e) => transfer.set_state(msg::TransferState::Failed(e.to_string()))
+                    }
+                    report(Event::FileProgress(transfer));
+                    Ok(())
+                },
+                tcp::Protocol::Resend => {
+                    eprintln!("REQUESTED TO RESEND");
+                    let at_host = peers_at(&peers, stream.peer_addr()?.ip());
+
+                    let Ok(id) = data.as_slice().try_into().map(u64::from_be_bytes) else {
+                        stream.respond(&[1u8])?;
+                        return Err(tcp::Error::Protocol("Resend request does not carry a message ID"))
+                    };
+                    stream.respond(&[0u8])?;
+
+                    // message IDs are random, so only the peer it was sent to has it
+                    let resend = at_host.into_iter().find_map(|peer| {
+                        let msg = peers.find_msg(&peer.address(), id).filter(|msg| msg.author() == "You")?;
+                        Some((msg, peer))
+                    });
+                    if let Some((msg, peer)) = resend {
+                        eprintln!("RESEND MESSAGE");
+                        send_message(Arc::clone(&peers), peer, msg, Arc::clone(&report));
+                    }
+                    Ok(())
+                },
+                _ => {
+                    stream.respond(&[1u8])?;
+                    Err(tcp::Error::Protocol("Other end sent an unknown command"))
+                }
+            });
+
+            match result {
+                Ok(()) => (),
+                // connections that drop or time out, like port scans, are not worth bothering the user with
+                Err(tcp::Error::Io(e)) => eprintln!("REQUEST FROM {from} FAILED: {e}"),
+                Err(e) => {
+                    eprintln!("REQUEST FROM {from} FAILED: {e}");
+                    report(Event::Error(format!("Could not handle a request from {from}"), e));
+                }
+            }
+        });
+    }
+}
+
+/// Send `msg` to `peer`, keeping its status in `peers` up to date.
+/// If `peer` is offline, or older messages to it are still queued, `msg` goes into `OUTBOX` instead.
+pub fn send_message(peers: Arc<PeerStore>, peer: msg::Recipient, msg: msg::Message, report: Reporter) {
+    let host = peer.ip();
+    let addr = (host.as_str(), peer.port());
+    let address = peer.address();
+    if OUTBOX.has_queued(&address) || tcp::check_availability(addr).is_err() {
+        eprintln!("PEER OFFLINE, QUEUEING MESSAGE");
+        OUTBOX.queue(address.clone(), msg.clone());
+        peers.set_status(&address, msg.id(), msg::MessageStatus::Queued);
+        return
+    }
+
+    let result = private_key(&peers, &peer, &report)
+        .and_then(|key| tcp::encrypted_send(addr, msg.id(), &msg.to_bytes(), key));
+
+    let status = match result {
+        Ok(tcp::Delivery::Delivered) => msg::MessageStatus::Delivered,
+        Ok(tcp::Delivery::Accepted) => msg::MessageStatus::Sent,
+        // the identity warning already tells the user what went wrong
+        Err(tcp::Error::IdentityMismatch) => msg::MessageStatus::Failed,
+        Err(e) => {
+            eprintln!("MESSAGE FAILED: {e}");
+            report(Event::Error(format!("Could not send a message to {}", peer.full_string()), e));
+            msg::MessageStatus::Failed
+        }
+    };
+    peers.set_status(&address, msg.id(), status);
+}
+
+/// Offer the file at `path` to `peer` and send it once accepted, reporting progress as `Event::FileProgress`.
+pub fn send_file(peers: Arc<PeerStore>, peer: msg::Recipient, path: PathBuf, report: Reporter) {
+    let host = peer.ip();
+    let addr = (host.as_str(), peer.port());
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => path.to_string_lossy().to_string()
+    };
+    let size = std::fs::metadata(&path).map(|meta| meta.len()).unwrap_or(0);
+    let mut transfer = msg::Transfer::new(peer.address(), name, size, false);
+    report(Event::FileProgress(transfer.clone()));
+
+    let result = private_key(&peers, &peer, &report).and_then(|key| {
+        let mut running = transfer.clone();
+        running.set_state(msg::TransferState::Running);
+        file::send_file(addr, &path, key, |sent| {
+            running.set_done(sent);
+            report(Event::FileProgress(running.clone()));
+        })
+    });
+
+    match result {
+        Ok(()) => {
+            eprintln!("FILE SENT");
+            transfer.set_done(size);
+            transfer.set_state(msg::TransferState::Done);
+            record_transfer(&peers, &transfer);
+        },
+        Err(tcp::Error::Declined) => transfer.set_state(msg::TransferState::Declined),
+        Err(e) => transfer.set_state(msg::TransferState::Failed(e.to_string()))
+    }
+    report(Event::FileProgress(transfer));
+}
+
+/// The private key for `peer`, agreeing on a new one and storing it in `peers` if there is none yet
+fn private_key(peers: &PeerStore, peer: &msg::Recipient, report: &Reporter) -> Result<Vec<u8>, tcp::Error> {
+    if let Some(key) = peer.private_key() {
+        return Ok(key);
+    }
+
+    let stored = make_keypair(peer.ip(), peer.port(), peer.identity()).and_then(|(identity, key)| {
+        peers.store_key(&peer.ip(), peer.port(), identity, key.clone())?;
+        Ok(key)
+    });
+    match stored {
+        Ok(key) => {
+            eprintln!("KEY STORED");
+            Ok(key)
+        },
+        Err(e) => {
+            if matches!(e, tcp::Error::IdentityMismatch) {
+                identity_mismatch(peers, report, peer.address());
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Flag the peer with `address` as having changed keys if it was verified, and tell the user
+fn identity_mismatch(peers: &PeerStore, report: &Reporter, address: String) {
+    peers.modify(&address, |peer| if peer.verified() { peer.set_key_changed(true) });
+    report(Event::IdentityMismatch(address));
+}
+
+/// Note a finished file transfer in the chat history of its peer, which it names by address
+fn record_transfer(peers: &PeerStore, transfer: &msg::Transfer) {
+    let Some(peer) = peers.get(&transfer.peer()) else {return};
+    let (author, content) = match transfer.path() {
+        Some(path) => (peer.ip(), format!("Sent a file: {} (saved to {})", transfer.name(), path.to_string_lossy())),
+        None => (String::from("You"), format!("Sent a file: {}", transfer.name()))
+    };
+    peers.push_msg(&transfer.peer(), msg::Message::new(author, content));
+}
+
+/// The safety number shared by us and `peer`, `None` if no identity is pinned for it yet
+pub fn fingerprint(peer: &msg::Recipient) -> Option<String> {
+    let ours = kem::identity_public(&IDENTITY);
+    peer.identity().map(|theirs| kem::fingerprint(&ours, &theirs))
+}
+
+/// Agree on a private key with `ip` listening on `port`, returns the identity it presented and the private key.
+/// The port this install listens on is sent along, so the other end knows where to reach it.
+///
+/// If `pinned` is set and the other end presents a different identity, this fails with
+/// `tcp::Error::IdentityMismatch` and no key is made.
+pub fn make_keypair(ip: impl ToString, port: u16, pinned: Option<Vec<u8>>) -> Result<(Vec<u8>, Vec<u8>), tcp::Error> {
+    let host = ip.to_string();
+    let addr = (host.as_str(), port);
+
+    let secret = kem::generate_secret();
+    let public_key = kem::public_key(&secret);
+    let bundle = kem::sign_key(&IDENTITY, &public_key, &[]);
+    let their_bundle = tcp::send_public_key(addr, bundle)?;
+
+    let (identity, their_key) = kem::verify_key(&their_bundle, &public_key)?;
+    if pinned.is_some_and(|pinned| pinned != identity) {
+        return Err(tcp::Error::IdentityMismatch);
+    }
+
+    let private_key = kem::agree(&secret, &their_key, &public_key, &their_key)?;
+    let listen_port = LISTEN_PORT.load(Ordering::Relaxed).to_be_bytes();
+    let bundle = kem::sign_key(&IDENTITY, &public_key, &[their_key.as_slice(), &listen_port].concat());
+    tcp::send_mixed_key(addr, [bundle.as_slice(), &listen_port].concat())?;
+
+    Ok((identity, private_key))
+}
+
+/// The known peers on the host connecting from `addr`, one for every instance it runs. Peers added
+/// by host name are found by the addresses the name resolves to, if none was added by the address.
+fn peers_at(peers: &PeerStore, addr: IpAddr) -> Vec<msg::Recipient> {
+    let addr = addr.to_canonical();
+    let (by_ip, by_name): (Vec<msg::Recipient>, Vec<msg::Recipient>) = peers.peers().into_iter()
+        .partition(|peer| msg::host_ip(&peer.ip()).is_some());
+
+    let by_ip: Vec<msg::Recipient> = by_ip.into_iter().filter(|peer| msg::host_ip(&peer.ip()) == Some(addr)).collect();
+    if !by_ip.is_empty() {
+        return by_ip
+    }
+    by_name.into_iter().filter(|peer| resolve(&peer.ip()).contains(&addr)).collect()
+}
+
+/// Every address the host name `host` resolves to
+fn resolve(host: &str) -> Vec<IpAddr> {
+    match (host, 0).to_socket_addrs() {
+        Ok(addrs) => addrs.map(|addr| addr.ip().to_canonical()).collect(),
+        Err(_) => Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,103 @@
+#![cfg(test)]
+
+use super::*;
+
+fn message(id: u64) -> msg::Message {
+    msg::Message::with_details(id, String::from("You"), format!("message {id}"), 0, 0)
+}
+
+#[test]
+fn queued_messages_are_taken_in_order() {
+    let outbox = Outbox::default();
+    outbox.queue(String::from("10.0.0.2"), message(1));
+    outbox.queue(String::from("10.0.0.3"), message(2));
+    outbox.queue(String::from("10.0.0.2"), message(3));
+    outbox.queue(String::from("10.0.0.2"), message(1));
+    assert!(outbox.has_queued("10.0.0.2"));
+
+    let taken: Vec<u64> = outbox.take("10.0.0.2").iter().map(|msg| msg.id()).collect();
+    assert_eq!(taken, vec![1, 3], "a message queued twice is sent once");
+    assert!(!outbox.has_queued("10.0.0.2"));
+    assert!(outbox.take("10.0.0.2").is_empty());
+    assert_eq!(outbox.take("10.0.0.3").len(), 1);
+}
+
+#[test]
+fn forgotten_peers_are_dropped() {
+    let outbox = Outbox::default();
+    outbox.queue(String::from("10.0.0.2"), message(1));
+    outbox.queue(String::from("10.0.0.3"), message(2));
+    outbox.forget("10.0.0.2");
+    assert!(!outbox.has_queued("10.0.0.2"));
+    assert!(outbox.has_queued("10.0.0.3"));
+    assert!(!outbox.backoff.lock().unwrap().contains_key("10.0.0.2"));
+}
+
+#[test]
+fn offline_peers_are_tried_less_and_less_often() {
+    let outbox = Outbox::default();
+    outbox.queue(String::from("10.0.0.2"), message(1));
+    assert!(outbox.due().is_empty(), "nobody is tried right after queueing");
+
+    let delay = |outbox: &Outbox| outbox.backoff.lock().unwrap()["10.0.0.2"].1;
+    assert_eq!(delay(&outbox), FIRST_BACKOFF);
+    outbox.back_off("10.0.0.2");
+    assert_eq!(delay(&outbox), FIRST_BACKOFF * 2);
+    for _ in 0..10 {
+        outbox.back_off("10.0.0.2");
+    }
+    assert_eq!(delay(&outbox), MAX_BACKOFF);
+
+    outbox.backoff.lock().unwrap().get_mut("10.0.0.2").unwrap().0 = Instant::now();
+    assert_eq!(outbox.due(), vec![String::from("10.0.0.2")]);
+    outbox.take("10.0.0.2");
+    assert!(outbox.due().is_empty(), "taken peers start over");
+}
+
+#[test]
+fn instances_on_one_host_are_told_apart() {
+    // two instances on this computer, each listening on a port of its own
+    let instances: Vec<TcpListener> = (0..2).map(|_| TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap()).collect();
+    let ports: Vec<u16> = instances.iter().map(|listener| listener.local_addr().unwrap().port()).collect();
+
+    let peers = Arc::new(PeerStore::default());
+    for (at, port) in ports.iter().enumerate() {
+        let mut peer = msg::Recipient::from("127.0.0.1");
+        peer.set_port(*port);
+        peer.set_identity(Some(vec![at as u8; 32]));
+        peer.set_private_key(vec![at as u8 + 1; 32]);
+        assert!(peers.add(peer), "both instances can be added");
+    }
+    let mut again = msg::Recipient::from("127.0.0.1");
+    again.set_port(ports[1]);
+    assert!(!peers.add(again), "the same instance cannot be added twice");
+
+    let (heard, received) = mpsc::channel();
+    peers.watch(move |change| if let crate::peers::Change::Message(address, id) = change {
+        heard.send((address, id)).unwrap_or(());
+    });
+    let mismatches = Arc::new(Mutex::new(0));
+    let counted = Arc::clone(&mismatches);
+    let report: Reporter = Arc::new(move |event| if let Event::IdentityMismatch(_) = event {
+        *counted.lock().unwrap() += 1;
+    });
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let ours = listener.local_addr().unwrap().port();
+    let store = Arc::clone(&peers);
+    thread::spawn(move || handle_requests(listener, Arc::new(kem::generate_secret()), store, report));
+
+    // both connect from 127.0.0.1, only the key they seal with says which one it is
+    for (at, port) in ports.iter().enumerate().rev() {
+        let msg = msg::Message::new(String::from("You"), format!("from instance {at}"));
+        let delivery = tcp::encrypted_send(("127.0.0.1", ours), msg.id(), &msg.to_bytes(), vec![at as u8 + 1; 32]);
+        assert!(matches!(delivery, Ok(tcp::Delivery::Delivered)));
+
+        let address = msg::address("127.0.0.1", *port);
+        assert_eq!(received.recv_timeout(Duration::from_secs(5)).unwrap(), (address.clone(), msg.id()));
+        let history = peers.history(&address).unwrap();
+        assert_eq!(history.history().len(), 1);
+        assert_eq!(history.history()[0].author(), "127.0.0.1");
+        assert_eq!(history.history()[0].content(), format!("from instance {at}"));
+    }
+    assert_eq!(*mismatches.lock().unwrap(), 0);
+}
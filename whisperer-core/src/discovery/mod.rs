@@ -0,0 +1,146 @@
+//! Finding other Whisperer instances on the local network
+//!
+//! While discovery is turned on, every instance broadcasts a small UDP datagram on `DISCOVERY_PORT`
+//! every `ANNOUNCE_INTERVAL`: the `MAGIC` bytes, the protocol version, the port it listens for
+//! messages on as a big-endian u16, and its host name. The same socket listens for the
+//! announcements of the other instances. Nothing is sent or received while discovery is off.
+#![allow(unused)]
+use crate::tcp::PROTOCOL_VERSION;
+use std::{
+    env::var,
+    io::{Error, ErrorKind},
+    net::{Ipv4Addr, UdpSocket},
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
+    thread,
+    time::{Duration, Instant}
+};
+
+/// The UDP port announcements are broadcast on
+pub const DISCOVERY_PORT: u16 = 9997;
+/// How often this instance announces itself
+pub const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(5);
+/// How long an instance is listed after its last announcement
+pub const FORGET_AFTER: Duration = Duration::from_secs(20);
+/// The bytes every announcement starts with
+const MAGIC: &[u8] = b"whisperer";
+/// The longest host name that is announced, in bytes
+const MAX_NAME_LENGTH: usize = 64;
+
+/// Another instance that announced itself
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instance {
+    pub ip: String,
+    pub name: String,
+    pub port: u16,
+    pub seen: Instant
+}
+
+/// The datagram an instance broadcasts
+#[derive(Clone, Debug, PartialEq)]
+pub struct Announcement {
+    pub name: String,
+    pub port: u16
+}
+impl Announcement {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut end = self.name.len().min(MAX_NAME_LENGTH);
+        while !self.name.is_char_boundary(end) {end -= 1}
+        let name = &self.name[..end];
+        [
+            MAGIC,
+            &[PROTOCOL_VERSION],
+            &self.port.to_be_bytes(),
+            name.as_bytes()
+        ].concat()
+    }
+    /// Read an announcement, `None` if the datagram is not one or uses another protocol version
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let rest = bytes.strip_prefix(MAGIC)?;
+        let (version, rest) = rest.split_first()?;
+        if *version != PROTOCOL_VERSION {return None}
+        let (port, name) = rest.split_at_checked(2)?;
+        if name.len() > MAX_NAME_LENGTH {return None}
+
+        Some(Self {
+            name: String::from_utf8(name.to_vec()).ok()?,
+            port: u16::from_be_bytes(port.try_into().ok()?)
+        })
+    }
+}
+
+/// A running discovery service, it stops when dropped
+pub struct Discovery {
+    running: Arc<AtomicBool>
+}
+impl Discovery {
+    /// Announce `announcement` on `discovery_port` and call `found` for every announcement heard,
+    /// including repeated ones and the ones this computer sent itself.
+    pub fn start(announcement: Announcement, discovery_port: u16, found: impl Fn(Instance) + Send + 'static) -> Result<Self, Error> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, discovery_port))?;
+        socket.set_broadcast(true)?;
+        socket.set_read_timeout(Some(Duration::from_secs(1)))?;
+
+        let running = Arc::new(AtomicBool::new(true));
+        let still_running = Arc::clone(&running);
+        thread::spawn(move || {
+            let datagram = announcement.to_bytes();
+            let mut last_announced: Option<Instant> = None;
+            let mut buf = [0u8; 128];
+
+            while still_running.load(Ordering::Relaxed) {
+                if last_announced.is_none_or(|last| last.elapsed() >= ANNOUNCE_INTERVAL) {
+                    socket.send_to(&datagram, (Ipv4Addr::BROADCAST, discovery_port)).unwrap_or_else(|e| {
+                        eprintln!("COULD NOT ANNOUNCE: {e}");
+                        0
+                    });
+                    last_announced = Some(Instant::now());
+                }
+
+                let (size, from) = match socket.recv_from(&mut buf) {
+                    Ok(received) => received,
+                    Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => continue,
+                    Err(e) => {
+                        eprintln!("DISCOVERY STOPPED: {e}");
+                        break
+                    }
+                };
+                if let Some(heard) = Announcement::from_bytes(&buf[..size]) {
+                    found(Instance {
+                        ip: from.ip().to_string(),
+                        name: heard.name,
+                        port: heard.port,
+                        seen: Instant::now()
+                    });
+                }
+            }
+        });
+
+        Ok(Self {running})
+    }
+    pub fn stop(&self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+impl Drop for Discovery {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// The name of this computer, `None` if it cannot be found
+pub fn host_name() -> Option<String> {
+    let mut name: Option<String> = None;
+    #[cfg(target_os = "linux")]
+    {
+        name = std::fs::read_to_string("/etc/hostname").ok()
+            .or_else(|| var("HOSTNAME").ok());
+    }
+    #[cfg(target_os = "windows")]
+    {
+        name = var("COMPUTERNAME").ok();
+    }
+    name.map(|name| name.trim().to_string()).filter(|name| !name.is_empty())
+}
+
+#[cfg(test)]
+mod tests;
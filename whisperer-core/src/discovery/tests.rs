@@ -0,0 +1,31 @@
+#![cfg(test)]
+
+use super::*;
+
+#[test]
+fn announcements_round_trip() {
+    let announcement = Announcement {name: String::from("büro-pc"), port: 9998};
+    let read = Announcement::from_bytes(&announcement.to_bytes()).unwrap();
+
+    assert_eq!(read, announcement);
+}
+
+#[test]
+fn long_names_are_cut_on_char_boundaries() {
+    let announcement = Announcement {name: "永".repeat(30), port: 9998};
+    let read = Announcement::from_bytes(&announcement.to_bytes()).unwrap();
+
+    assert!(read.name.len() <= MAX_NAME_LENGTH);
+    assert!(announcement.name.starts_with(&read.name));
+}
+
+#[test]
+fn foreign_datagrams_are_ignored() {
+    let mut other_version = Announcement {name: String::from("pc"), port: 9998}.to_bytes();
+    other_version[MAGIC.len()] = PROTOCOL_VERSION + 1;
+
+    assert!(Announcement::from_bytes(&other_version).is_none());
+    assert!(Announcement::from_bytes(b"M-SEARCH * HTTP/1.1").is_none());
+    assert!(Announcement::from_bytes(MAGIC).is_none());
+    assert!(Announcement::from_bytes(&[]).is_none());
+}
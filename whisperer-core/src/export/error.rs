@@ -0,0 +1,22 @@
+use std::fmt;
+
+/// Why an archive could not be imported
+#[derive(Debug)]
+pub enum Error {
+    /// The file is not JSON, it stops making sense at this byte
+    Json(usize),
+    /// The file is JSON, but not an archive made by `to_json`
+    NotAnArchive,
+    /// The archive was made by a newer version, with its version
+    Newer(u64)
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json(at) => write!(f, "File is not JSON, it breaks off at byte {at}"),
+            Self::NotAnArchive => write!(f, "File is not a chat history exported from Whisperer"),
+            Self::Newer(version) => write!(f, "Chat history was exported by a newer version (format {version})")
+        }
+    }
+}
+impl std::error::Error for Error {}
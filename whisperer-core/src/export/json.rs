@@ -0,0 +1,240 @@
+//! Just enough JSON to write archives and read them back
+//!
+//! Numbers are kept as the text they were written as, so IDs and timestamps do not lose precision.
+
+/// How deep arrays and objects may be nested, so a hostile file cannot exhaust the stack
+const MAX_DEPTH: usize = 64;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    /// The members of an object, in the order they were written
+    Object(Vec<(String, Value)>)
+}
+impl Value {
+    /// The member `key` of an object, `None` if this is not an object or it has no such member
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Self::Object(members) => members.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None
+        }
+    }
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(string) => Some(string),
+            _ => None
+        }
+    }
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Self::Number(number) => number.parse().ok(),
+            _ => None
+        }
+    }
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Self::Array(values) => Some(values),
+            _ => None
+        }
+    }
+}
+
+/// `string` as a JSON string literal
+pub fn string(string: &str) -> String {
+    let mut json = String::from("\"");
+    for c in string.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c)
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// `string` as a JSON string literal, or `null`
+pub fn optional(string: Option<&str>) -> String {
+    string.map_or(String::from("null"), self::string)
+}
+
+/// Read a single JSON value, the error is the byte offset it stopped making sense at
+pub fn parse(text: &str) -> Result<Value, usize> {
+    let mut parser = Parser {bytes: text.as_bytes(), at: 0};
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    match parser.at == parser.bytes.len() {
+        true => Ok(value),
+        false => Err(parser.at)
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    at: usize
+}
+impl Parser<'_> {
+    fn value(&mut self, depth: usize) -> Result<Value, usize> {
+        if depth > MAX_DEPTH {
+            return Err(self.at)
+        }
+        self.skip_whitespace();
+        match self.bytes.get(self.at) {
+            Some(b'{') => self.object(depth),
+            Some(b'[') => self.array(depth),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(b't') => self.literal("true", Value::Bool(true)),
+            Some(b'f') => self.literal("false", Value::Bool(false)),
+            Some(b'n') => self.literal("null", Value::Null),
+            _ => Err(self.at)
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Value, usize> {
+        let mut members: Vec<(String, Value)> = Vec::new();
+        self.at += 1;
+        self.skip_whitespace();
+        if self.eat(b'}') {
+            return Ok(Value::Object(members))
+        }
+        loop {
+            self.skip_whitespace();
+            if self.bytes.get(self.at) != Some(&b'"') {
+                return Err(self.at)
+            }
+            let name = self.string()?;
+            self.skip_whitespace();
+            if !self.eat(b':') {
+                return Err(self.at)
+            }
+            members.push((name, self.value(depth + 1)?));
+            self.skip_whitespace();
+            if self.eat(b'}') {
+                return Ok(Value::Object(members))
+            }
+            if !self.eat(b',') {
+                return Err(self.at)
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Value, usize> {
+        let mut values: Vec<Value> = Vec::new();
+        self.at += 1;
+        self.skip_whitespace();
+        if self.eat(b']') {
+            return Ok(Value::Array(values))
+        }
+        loop {
+            values.push(self.value(depth + 1)?);
+            self.skip_whitespace();
+            if self.eat(b']') {
+                return Ok(Value::Array(values))
+            }
+            if !self.eat(b',') {
+                return Err(self.at)
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, usize> {
+        self.at += 1;
+        let mut string: Vec<u8> = Vec::new();
+        loop {
+            let Some(&byte) = self.bytes.get(self.at) else { return Err(self.at) };
+            self.at += 1;
+            match byte {
+                b'"' => return String::from_utf8(string).map_err(|_| self.at),
+                b'\\' => {
+                    let Some(&escape) = self.bytes.get(self.at) else { return Err(self.at) };
+                    self.at += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(self.at - 1)
+                    };
+                    string.extend_from_slice(c.encode_utf8(&mut [0u8; 4]).as_bytes());
+                },
+                0..=0x1f => return Err(self.at - 1),
+                byte => string.push(byte)
+            }
+        }
+    }
+
+    /// The character of a `\u` escape, which takes two of them for characters outside the basic plane
+    fn unicode_escape(&mut self) -> Result<char, usize> {
+        let high = self.hex()?;
+        if !(0xd800..0xdc00).contains(&high) {
+            return char::from_u32(high).ok_or(self.at)
+        }
+        if self.bytes.get(self.at..self.at + 2) != Some(b"\\u") {
+            return Err(self.at)
+        }
+        self.at += 2;
+        let low = self.hex()?;
+        if !(0xdc00..0xe000).contains(&low) {
+            return Err(self.at)
+        }
+        char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)).ok_or(self.at)
+    }
+
+    fn hex(&mut self) -> Result<u32, usize> {
+        let digits = self.bytes.get(self.at..self.at + 4).ok_or(self.at)?;
+        let digits = std::str::from_utf8(digits).map_err(|_| self.at)?;
+        let value = u32::from_str_radix(digits, 16).map_err(|_| self.at)?;
+        self.at += 4;
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Result<Value, usize> {
+        let start = self.at;
+        while self.bytes.get(self.at).is_some_and(|byte| matches!(byte, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')) {
+            self.at += 1;
+        }
+        let number = std::str::from_utf8(&self.bytes[start..self.at]).map_err(|_| start)?;
+        match number.parse::<f64>() {
+            Ok(_) => Ok(Value::Number(number.to_string())),
+            Err(_) => Err(start)
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value, usize> {
+        match self.bytes[self.at..].starts_with(word.as_bytes()) {
+            true => {
+                self.at += word.len();
+                Ok(value)
+            },
+            false => Err(self.at)
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        let matches = self.bytes.get(self.at) == Some(&byte);
+        if matches {
+            self.at += 1;
+        }
+        matches
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.bytes.get(self.at).is_some_and(|byte| matches!(byte, b' ' | b'\t' | b'\n' | b'\r')) {
+            self.at += 1;
+        }
+    }
+}
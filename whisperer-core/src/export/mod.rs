@@ -0,0 +1,227 @@
+//! Chat histories as files people can keep, read, and bring back
+//!
+//! `to_json` writes an archive of every peer with its messages, their authors, aliases and timestamps,
+//! which `from_json` reads back for `PeerStore::merge`. The archive is an object with `VERSION` as
+//! `whisperer_export`, the time it was made as `exported_at` and a `peers` array. Every peer has its
+//! `host`, `port`, `alias` and `messages`, and every message its `id`, `author` (`You` for messages we
+//! wrote, the host of the peer otherwise), the `alias` the author had, `sent_at` and `received_at` in
+//! milliseconds since the unix epoch, `status` and `content`. Keys are never exported.
+//!
+//! `to_markdown` and `to_text` write transcripts that are only meant to be read.
+use crate::msg;
+
+mod json;
+mod error;
+
+pub use error::Error;
+pub use json::string as json_string;
+
+/// The version of the archive written by this build
+pub const VERSION: u64 = 1;
+
+/// What a chat history can be exported as
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Json,
+    Markdown,
+    Text
+}
+impl Format {
+    /// The extension of files in this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Markdown => "md",
+            Self::Text => "txt"
+        }
+    }
+}
+
+/// Write `histories` in `format`
+pub fn export(histories: &[msg::ChatHistory], format: Format) -> String {
+    match format {
+        Format::Json => to_json(histories),
+        Format::Markdown => to_markdown(histories),
+        Format::Text => to_text(histories)
+    }
+}
+
+/// An archive of `histories`, one message per line
+pub fn to_json(histories: &[msg::ChatHistory]) -> String {
+    let peers: Vec<String> = histories.iter().map(|history| {
+        let peer = history.peer();
+        let messages: Vec<String> = history.history().iter().map(|msg| format!(
+            "{{\"id\":{},\"author\":{},\"alias\":{},\"sent_at\":{},\"received_at\":{},\"status\":{},\"content\":{}}}",
+            msg.id(),
+            json::string(&msg.author()),
+            json::optional(author_alias(&peer, msg).as_deref()),
+            msg.sent_at(),
+            msg.received_at(),
+            json::string(msg.status().name()),
+            json::string(&msg.content())
+        )).collect();
+        let messages = match messages.is_empty() {
+            true => String::new(),
+            false => format!("\n        {}\n      ", messages.join(",\n        "))
+        };
+        format!(
+            "    {{\n      \"host\": {},\n      \"port\": {},\n      \"alias\": {},\n      \"messages\": [{messages}]\n    }}",
+            json::string(&peer.ip()),
+            peer.port(),
+            json::optional(peer.alias().as_deref())
+        )
+    }).collect();
+
+    format!(
+        "{{\n  \"whisperer_export\": {VERSION},\n  \"exported_at\": {},\n  \"peers\": [\n{}\n  ]\n}}\n",
+        msg::now(),
+        peers.join(",\n")
+    )
+}
+
+/// Read an archive made by `to_json`. Messages that were still on their way when it was made
+/// never will be, so they are read as failed.
+pub fn from_json(text: &str) -> Result<Vec<msg::ChatHistory>, Error> {
+    let archive = json::parse(text).map_err(Error::Json)?;
+    let version = archive.get("whisperer_export").and_then(|version| version.as_u64()).ok_or(Error::NotAnArchive)?;
+    if version > VERSION {
+        return Err(Error::Newer(version))
+    }
+
+    let peers = archive.get("peers").and_then(|peers| peers.as_array()).ok_or(Error::NotAnArchive)?;
+    peers.iter().map(|peer| read_peer(peer).ok_or(Error::NotAnArchive)).collect()
+}
+
+fn read_peer(value: &json::Value) -> Option<msg::ChatHistory> {
+    let host = value.get("host")?.as_str()?;
+    if !msg::is_valid_host(host) {
+        return None
+    }
+    let mut peer = msg::Recipient::from(host);
+    if let Some(port) = value.get("port") {
+        peer.set_port(u16::try_from(port.as_u64()?).ok().filter(|port| *port != 0)?);
+    }
+    peer.set_alias(optional_str(value.get("alias"))?.map(String::from));
+
+    let mut history = msg::ChatHistory::new(peer);
+    for msg in value.get("messages")?.as_array()? {
+        let author = msg.get("author")?.as_str()?;
+        let time = |name: &str| match msg.get(name) {
+            Some(time) => time.as_u64(),
+            None => Some(0)
+        };
+        let mut read = msg::Message::with_details(
+            msg.get("id")?.as_u64()?,
+            // anything not written by us was written by the peer, whatever host it had then
+            match author {
+                "You" => String::from("You"),
+                _ => host.to_string()
+            },
+            msg.get("content")?.as_str()?.to_string(),
+            time("sent_at")?,
+            time("received_at")?
+        );
+        let status = match msg.get("status") {
+            Some(status) => msg::MessageStatus::from_name(status.as_str()?)?,
+            None => msg::MessageStatus::default()
+        };
+        read.set_status(match status {
+            msg::MessageStatus::Pending | msg::MessageStatus::Queued => msg::MessageStatus::Failed,
+            status => status
+        });
+        history.push_msg(read);
+    }
+    Some(history)
+}
+
+/// A string or `null`, `None` if it is something else. A missing value counts as `null`.
+fn optional_str(value: Option<&json::Value>) -> Option<Option<&str>> {
+    match value {
+        None | Some(json::Value::Null) => Some(None),
+        Some(value) => value.as_str().map(Some)
+    }
+}
+
+/// A transcript of `histories` in Markdown, with a heading for every peer and every day
+pub fn to_markdown(histories: &[msg::ChatHistory]) -> String {
+    let mut transcript = String::new();
+    for history in histories {
+        let peer = history.peer();
+        transcript.push_str(&format!("# {}\n", escape_markdown(&peer.full_string())));
+
+        let mut day: Option<String> = None;
+        for msg in history.history().iter() {
+            let time = shown_time(msg);
+            let msg_day = msg::format_day(time);
+            if msg_day.is_some() && msg_day != day {
+                transcript.push_str(&format!("\n## {}\n", msg_day.clone().unwrap_or_default()));
+                day = msg_day;
+            }
+
+            let name = escape_markdown(&author_name(&peer, msg));
+            match msg::format_time(time) {
+                Some(time) => transcript.push_str(&format!("\n**{name}** {time}  \n")),
+                None => transcript.push_str(&format!("\n**{name}**  \n"))
+            }
+            let lines: Vec<String> = msg.content().lines().map(escape_markdown).collect();
+            transcript.push_str(&lines.join("  \n"));
+            transcript.push('\n');
+        }
+        transcript.push('\n');
+    }
+    transcript
+}
+
+/// A transcript of `histories` in plain text, a line per message like `listen` prints them
+pub fn to_text(histories: &[msg::ChatHistory]) -> String {
+    let mut transcript = String::new();
+    for history in histories {
+        let peer = history.peer();
+        transcript.push_str(&format!("{}\n\n", peer.full_string()));
+        for msg in history.history().iter() {
+            let time = msg::format_date_time(shown_time(msg)).unwrap_or_else(|| String::from("-"));
+            // later lines of a message are indented, so every message starts at the edge
+            let content = msg.content().lines().collect::<Vec<&str>>().join("\n    ");
+            transcript.push_str(&format!("{time} [{}] {content}\n", author_name(&peer, msg)));
+        }
+        transcript.push('\n');
+    }
+    transcript
+}
+
+/// The alias of the author of `msg`, which is `peer` unless we wrote it
+fn author_alias(peer: &msg::Recipient, msg: &msg::Message) -> Option<String> {
+    match msg.author() == "You" {
+        true => None,
+        false => peer.alias()
+    }
+}
+
+/// The name the author of `msg` is shown with
+fn author_name(peer: &msg::Recipient, msg: &msg::Message) -> String {
+    author_alias(peer, msg).unwrap_or_else(|| msg.author())
+}
+
+/// When the message was written, or received if that is not known
+fn shown_time(msg: &msg::Message) -> u64 {
+    match msg.sent_at() {
+        0 => msg.received_at(),
+        sent_at => sent_at
+    }
+}
+
+/// A line of `text` with everything Markdown could read as formatting escaped
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::new();
+    for (i, c) in text.chars().enumerate() {
+        let block = i == 0 && matches!(c, '#' | '-' | '+' | '>' | '=');
+        if block || matches!(c, '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '|' | '~') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,101 @@
+#![cfg(test)]
+
+use super::*;
+
+fn sample() -> msg::ChatHistory {
+    let mut peer = msg::Recipient::from("laptop.local");
+    peer.set_port(9999);
+    peer.set_alias(Some(String::from("desk")));
+    peer.set_private_key(vec![10u8; 32]);
+
+    let mut history = msg::ChatHistory::new(peer);
+    let mut ours = msg::Message::with_details(u64::MAX, String::from("You"), String::from("lunch? \"now\"\n\nor *later*"), 1_760_000_000_000, 1_760_000_000_000);
+    ours.set_status(msg::MessageStatus::Queued);
+    history.push_msg(ours);
+    history.push_msg(msg::Message::with_details(2, String::from("laptop.local"), String::from("# sure 🍜"), 1_760_000_060_000, 1_760_000_061_000));
+    history.push_msg(msg::Message::with_details(3, String::from("laptop.local"), String::from("from before timestamps"), 0, 0));
+    history
+}
+
+#[test]
+fn archives_survive_the_round_trip() {
+    let json = to_json(&[sample(), msg::ChatHistory::new(msg::Recipient::from("10.0.0.2"))]);
+    assert!(!json.contains("\"key\""), "keys are never exported");
+    let histories = from_json(&json).unwrap();
+    assert_eq!(histories.len(), 2);
+
+    let peer = histories[0].peer();
+    assert_eq!((peer.ip(), peer.port(), peer.alias()), (String::from("laptop.local"), 9999, Some(String::from("desk"))));
+    assert!(peer.private_key().is_none());
+    let messages = histories[0].history();
+    assert_eq!(messages.len(), 3);
+    assert_eq!((messages[0].id(), messages[0].author(), messages[0].content()), (u64::MAX, String::from("You"), String::from("lunch? \"now\"\n\nor *later*")));
+    assert_eq!(messages[0].status(), msg::MessageStatus::Failed, "messages on their way are not sent again");
+    assert_eq!((messages[1].author(), messages[1].content()), (String::from("laptop.local"), String::from("# sure 🍜")));
+    assert_eq!((messages[1].sent_at(), messages[1].received_at()), (1_760_000_060_000, 1_760_000_061_000));
+    assert_eq!(messages[1].status(), msg::MessageStatus::Delivered);
+
+    assert_eq!(histories[1].peer().ip(), "10.0.0.2");
+    assert!(histories[1].history().is_empty());
+}
+
+#[test]
+fn archives_name_authors_by_alias() {
+    let json = to_json(&[sample()]);
+    assert!(json.contains("\"author\":\"laptop.local\",\"alias\":\"desk\""));
+    assert!(json.contains("\"author\":\"You\",\"alias\":null"));
+    assert!(json.contains(&format!("\"whisperer_export\": {VERSION}")));
+}
+
+#[test]
+fn broken_or_foreign_archives_are_refused() {
+    assert!(matches!(from_json("{\"peers\": [}"), Err(Error::Json(11))));
+    assert!(matches!(from_json("[]"), Err(Error::NotAnArchive)));
+    assert!(matches!(from_json("{\"whisperer_export\": 99, \"peers\": []}"), Err(Error::Newer(99))));
+    assert!(matches!(from_json("{\"whisperer_export\": 1, \"peers\": [{\"host\": \"10.0.0.2\"}]}"), Err(Error::NotAnArchive)));
+    assert!(matches!(
+        from_json("{\"whisperer_export\": 1, \"peers\": [{\"host\": \"not a host\", \"messages\": []}]}"),
+        Err(Error::NotAnArchive)
+    ));
+    assert_eq!(from_json("{\"whisperer_export\": 1, \"peers\": []}").map(|histories| histories.len()).ok(), Some(0));
+}
+
+#[test]
+fn json_is_read() {
+    let value = json::parse(" {\"a\": [1, -2.5e3, true, null], \"b\": \"\\u00fc\\ud83c\\udf5c\\n\\/\", \"c\": {}} ").unwrap();
+    assert_eq!(value.get("a").and_then(|a| a.as_array()).map(|a| a.len()), Some(4));
+    assert_eq!(value.get("a").and_then(|a| a.as_array()).and_then(|a| a[0].as_u64()), Some(1));
+    assert_eq!(value.get("b").and_then(|b| b.as_str()), Some("ü🍜\n/"));
+    assert_eq!(value.get("c"), Some(&json::Value::Object(Vec::new())));
+    assert_eq!(json::parse("18446744073709551615").ok().and_then(|value| value.as_u64()), Some(u64::MAX), "numbers keep every digit");
+
+    assert_eq!(json::parse("{\"a\": 1,}"), Err(8));
+    assert_eq!(json::parse("\"tab\there\""), Err(4));
+    assert_eq!(json::parse("\"\\ud83c\""), Err(7), "a lone surrogate is not a character");
+    assert_eq!(json::parse("[1] 2"), Err(4));
+    assert!(json::parse(&"[".repeat(1000)).is_err(), "deep nesting is refused instead of overflowing the stack");
+}
+
+#[test]
+fn transcripts_read_like_the_chat() {
+    let markdown = to_markdown(&[sample()]);
+    assert!(markdown.starts_with("# desk (laptop.local:9999)\n"));
+    assert!(markdown.contains(&format!("\n## {}\n", msg::format_day(1_760_000_000_000).unwrap())));
+    assert!(markdown.contains(&format!("**You** {}  \nlunch? \"now\"  \n  \nor \\*later\\*\n", msg::format_time(1_760_000_000_000).unwrap())));
+    assert!(markdown.contains("**desk** "));
+    assert!(markdown.contains("\\# sure 🍜"), "text is not read as formatting");
+    assert!(markdown.contains("\n**desk**  \nfrom before timestamps\n"));
+
+    let text = to_text(&[sample()]);
+    assert!(text.starts_with("desk (laptop.local:9999)\n\n"));
+    assert!(text.contains(&format!("{} [You] lunch? \"now\"\n    \n    or *later*\n", msg::format_date_time(1_760_000_000_000).unwrap())));
+    assert!(text.contains("- [desk] from before timestamps\n"));
+}
+
+#[test]
+fn statuses_are_named() {
+    for status in [msg::MessageStatus::Pending, msg::MessageStatus::Sent, msg::MessageStatus::Delivered, msg::MessageStatus::Failed, msg::MessageStatus::Queued] {
+        assert_eq!(msg::MessageStatus::from_name(status.name()), Some(status));
+    }
+    assert_eq!(msg::MessageStatus::from_name("lost"), None);
+}
@@ -0,0 +1,334 @@
+//! The daemon: listens for other computers and keeps the peer store, whether a window is open or not
+//!
+//! Front ends attach to it over the loopback address (see the `ipc` module) instead of listening themselves.
+use super::{respond, Request, Update, TIMEOUT, TOKEN_SIZE};
+use crate::{comms, msg, notify, save, peers::{Change, PeerStore}, tcp};
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    sync::{atomic::Ordering, mpsc, Arc, Mutex},
+    thread
+};
+
+/// The command byte and payload of an update, shared by the queues of every front end
+type Frame = (u8, Arc<[u8]>);
+
+/// The state shared by every connection to the daemon
+pub struct Daemon {
+    token: Vec<u8>,
+    peers: Arc<PeerStore>,
+    /// Gets the events that no front end is attached for
+    fallback: comms::Reporter,
+    /// The queue of updates for every attached front end, each one is written by the thread that attached it
+    front_ends: Mutex<Vec<mpsc::Sender<Frame>>>,
+    /// File offers waiting for a front end to answer them, by transfer ID
+    offers: Mutex<HashMap<u64, comms::FileOffer>>
+}
+impl Daemon {
+    /// A daemon for `peers` that takes requests starting with `token`.
+    /// Every change to `peers` is sent to the attached front ends.
+    pub fn new(token: Vec<u8>, peers: Arc<PeerStore>, fallback: comms::Reporter) -> Arc<Self> {
+        let daemon = Arc::new(Self {
+            token,
+            peers: Arc::clone(&peers),
+            fallback,
+            front_ends: Mutex::new(Vec::new()),
+            offers: Mutex::new(HashMap::new())
+        });
+
+        let watcher = Arc::clone(&daemon);
+        peers.watch(move |change| {
+            if let Some(update) = watcher.update_for(change) {
+                watcher.broadcast(&update);
+            }
+        });
+        daemon
+    }
+
+    /// The update telling front ends about `change`, read from the store after it was made. `None` if the peer is gone by now.
+    fn update_for(&self, change: Change) -> Option<Update> {
+        Some(match change {
+            Change::Peer(address) => Update::Peer(self.peers.history(&address)?),
+            Change::Removed(address) => Update::Removed(address),
+            Change::Message(address, id) => Update::Message {
+                unread: self.peers.history(&address)?.unread(),
+                msg: self.peers.find_msg(&address, id)?,
+                address
+            },
+            Change::History(address) => {
+                let history = self.peers.history(&address)?;
+                Update::History {address, unread: history.unread(), statuses: history.statuses()}
+            }
+        })
+    }
+
+    /// Where the network threads report to: the attached front ends, or `fallback` if there are none
+    pub fn reporter(self: &Arc<Self>) -> comms::Reporter {
+        let daemon = Arc::clone(self);
+        Arc::new(move |event| daemon.report(event))
+    }
+
+    /// Whether any front end is attached
+    pub fn has_front_ends(&self) -> bool {
+        !self.front_ends.lock().unwrap().is_empty()
+    }
+
+    fn report(&self, event: comms::Event) {
+        if !self.has_front_ends() {
+            return (self.fallback)(event)
+        }
+
+        let update = match event {
+            comms::Event::IdentityMismatch(address) => Update::IdentityMismatch(address),
+            comms::Event::FileOffer(offer) => {
+                let transfer = offer.transfer();
+                self.offers.lock().unwrap().insert(transfer.id(), offer);
+                Update::FileOffer(transfer)
+            },
+            comms::Event::FileProgress(transfer) => {
+                if transfer.finished() {
+                    self.offers.lock().unwrap().remove(&transfer.id());
+                }
+                Update::FileProgress(transfer)
+            },
+            comms::Event::Error(context, e) => Update::Error(context, e.to_string())
+        };
+        self.broadcast(&update);
+    }
+
+    /// Queue `update` for every attached front end, forgetting the ones that are gone.
+    /// Nothing is written here, so a slow front end does not hold up the others or the peer store.
+    fn broadcast(&self, update: &Update) {
+        let (code, payload) = frame(update);
+        let payload: Arc<[u8]> = payload.into();
+        self.front_ends.lock().unwrap().retain(|queue| queue.send((code, Arc::clone(&payload))).is_ok());
+    }
+
+    /// Take requests on `listener` until it fails
+    pub fn serve(self: &Arc<Self>, listener: TcpListener) {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("COULD NOT ACCEPT FRONT END: {e}");
+                    continue
+                }
+            };
+
+            let daemon = Arc::clone(self);
+            thread::spawn(move || {
+                if let Err(e) = daemon.handle(stream) {
+                    eprintln!("FRONT END REQUEST FAILED: {e}");
+                }
+            });
+        }
+    }
+
+    fn handle(self: &Arc<Self>, mut stream: TcpStream) -> Result<(), tcp::Error> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        let (code, payload) = tcp::read_raw_frame(&mut stream)?;
+        let (token, fields) = payload.split_at_checked(TOKEN_SIZE).unwrap_or_default();
+        if !same_token(token, &self.token) {
+            respond(&mut stream, Err(String::from("Wrong token, the daemon was restarted")))?;
+            return Err(tcp::Error::Rejected)
+        }
+        let Some(request) = Request::from_bytes(code, fields) else {
+            respond(&mut stream, Err(String::from("Unknown request")))?;
+            return Err(tcp::Error::Protocol("Front end sent an unknown request"))
+        };
+
+        if let Request::Attach = request {
+            // changes are queued from before the snapshot is taken, so none are missed, and one that is in
+            // both only sets the same thing twice
+            let (queue, updates) = mpsc::channel();
+            self.front_ends.lock().unwrap().push(queue);
+            let snapshot = self.peers.histories();
+            eprintln!("FRONT END ATTACHED");
+            self.write_updates(stream, snapshot, updates);
+            return Ok(())
+        }
+
+        let result = self.run(request);
+        respond(&mut stream, result)
+    }
+
+    /// Send an attached front end every peer in `snapshot`, then the updates queued for it until it is gone
+    fn write_updates(&self, mut stream: TcpStream, snapshot: Vec<msg::ChatHistory>, updates: mpsc::Receiver<Frame>) {
+        let written = respond(&mut stream, Ok(Vec::new())).and_then(|()| {
+            for history in snapshot {
+                let (code, payload) = frame(&Update::Peer(history));
+                tcp::write_raw_frame(&mut stream, code, &payload)?;
+            }
+            for (code, payload) in updates {
+                tcp::write_raw_frame(&mut stream, code, &payload)?;
+            }
+            Ok(())
+        });
+        // the queue is dropped along with `updates`, so `broadcast` forgets it
+        if let Err(e) = written {
+            eprintln!("FRONT END DETACHED: {e}");
+        }
+    }
+
+    /// Do what a front end asked for, returns the result or the reason it failed
+    fn run(self: &Arc<Self>, request: Request) -> Result<Vec<u8>, String> {
+        let missing = |address: &str| format!("There is no peer called {address}");
+        match request {
+            Request::Ping | Request::Attach => Ok(Vec::new()),
+            Request::Add {host, port, alias} => {
+                let address = msg::address(&host, port);
+                if self.peers.contains(&address) {
+                    return Err(format!("{address} is already added"))
+                }
+                let (identity, key) = tcp::check_availability((host.as_str(), port))
+                    .and_then(|_| comms::make_keypair(host.clone(), port, None))
+                    .map_err(|e| e.to_string())?;
+
+                let mut peer = msg::Recipient::from(host);
+                peer.set_port(port);
+                peer.set_alias(alias);
+                peer.set_identity(Some(identity));
+                peer.set_private_key(key);
+                self.peers.add(peer.clone());
+                Ok(save::format::to_bytes(&[msg::ChatHistory::new(peer)]))
+            },
+            Request::Send(address, mut msg) => {
+                let peer = self.peers.get(&address).ok_or_else(|| missing(&address))?;
+                msg.set_status(msg::MessageStatus::Pending);
+                if !self.peers.push_msg(&address, msg.clone()) {
+                    self.peers.set_status(&address, msg.id(), msg::MessageStatus::Pending);
+                }
+
+                comms::send_message(Arc::clone(&self.peers), peer, msg.clone(), self.reporter());
+                let status = self.peers.find_msg(&address, msg.id()).map(|msg| msg.status()).unwrap_or_default();
+                Ok(vec![status.code()])
+            },
+            Request::SendFile(address, path) => {
+                let peer = self.peers.get(&address).ok_or_else(|| missing(&address))?;
+                let peers = Arc::clone(&self.peers);
+                let report = self.reporter();
+                thread::spawn(move || comms::send_file(peers, peer, path, report));
+                Ok(Vec::new())
+            },
+            Request::Alias(address, alias) => self.modify(&address, |peer| peer.set_alias(alias)),
+            Request::Verified(address, verified) => self.modify(&address, |peer| peer.set_verified(verified)),
+            Request::ForgetIdentity(address) => self.modify(&address, |peer| peer.forget_identity()),
+            Request::Muted(address, muted) => self.modify(&address, |peer| peer.set_muted(muted)),
+            Request::MarkRead(address) => {
+                self.peers.mark_read(&address);
+                Ok(Vec::new())
+            },
+            Request::ClearHistory(address) => {
+                comms::OUTBOX.forget(&address);
+                self.peers.clear_history(&address);
+                Ok(Vec::new())
+            },
+            Request::Remove(address) => {
+                comms::OUTBOX.forget(&address);
+                self.peers.remove(&address);
+                Ok(Vec::new())
+            },
+            Request::Passphrase {current, new} => {
+                save::set_passphrase(current.as_deref(), new.as_deref()).map_err(|e| e.to_string())?;
+                save::set_data(&self.peers);
+                Ok(Vec::new())
+            },
+            Request::Import(histories) => {
+                let added: usize = histories.into_iter().map(|history| self.peers.merge(history)).sum();
+                Ok((added as u64).to_be_bytes().to_vec())
+            },
+            Request::AnswerFile(id, accept) => match self.offers.lock().unwrap().remove(&id) {
+                Some(offer) => {
+                    offer.answer(accept);
+                    Ok(Vec::new())
+                },
+                None => Err(String::from("The file is no longer offered"))
+            }
+        }
+    }
+
+    fn modify(&self, address: &str, change: impl FnOnce(&mut msg::Recipient)) -> Result<Vec<u8>, String> {
+        match self.peers.modify(address, change) {
+            true => Ok(Vec::new()),
+            false => Err(format!("There is no peer called {address}"))
+        }
+    }
+}
+
+/// The command byte and payload `update` is sent as. An update too long for a frame would leave the
+/// front end out of step, so it is told about that instead.
+fn frame(update: &Update) -> (u8, Vec<u8>) {
+    let payload = update.to_bytes();
+    if payload.len() <= tcp::MAX_CONTENT_LENGTH {
+        return (update.code(), payload)
+    }
+    eprintln!("UPDATE IS TOO LONG TO SEND");
+    let error = Update::Error(String::from("Could not show a change, restart to see it"), String::from("It is too long to send"));
+    (error.code(), error.to_bytes())
+}
+
+/// Compare tokens without stopping at the first difference, so their contents cannot be timed
+fn same_token(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Run the daemon for the peers in the data directory, listening for other computers on `listen_port`.
+/// Everything is saved as soon as it changes, and new messages show a notification while no front end
+/// is attached to show them. Only returns if nothing could listen on the port.
+pub fn run(listen_port: u16, fallback: comms::Reporter) {
+    let bound = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).and_then(|listener| Ok((listener.local_addr()?.port(), listener)));
+    let (port, listener) = match bound {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("COULD NOT LISTEN FOR FRONT ENDS: {e}");
+            fallback(comms::Event::Error(String::from("Front ends cannot attach"), e.into()));
+            return
+        }
+    };
+
+    comms::LISTEN_PORT.store(listen_port, Ordering::Relaxed);
+    let (histories, problems) = save::get_data();
+    for problem in problems {
+        eprintln!("LOADING: {problem}");
+    }
+    let peers = Arc::new(PeerStore::new(histories));
+    for history in peers.histories().iter() {
+        for msg in history.history().iter() {
+            if msg.author() == "You" && msg.status() == msg::MessageStatus::Queued {
+                comms::OUTBOX.queue(history.peer().address(), msg.clone());
+            }
+        }
+    }
+    save::keep_saved(&peers);
+
+    let token: Vec<u8> = (0..TOKEN_SIZE).map(|_| rand::random()).collect();
+    let daemon = Daemon::new(token.clone(), Arc::clone(&peers), fallback);
+    if let Some(notifier) = notify::Notifier::session() {
+        let store = Arc::clone(&peers);
+        let quiet = Arc::clone(&daemon);
+        peers.watch(move |change| {
+            let Change::Message(address, id) = change else { return };
+            if quiet.has_front_ends() { return }
+            if let Some((summary, body)) = notify::message_notification(&store, &address, id) {
+                notifier.notify(&summary, &body);
+            }
+        });
+    }
+    if let Err(e) = save::set_daemon(port, &token) {
+        eprintln!("COULD NOT STORE THE DAEMON PORT: {e}");
+        daemon.report(comms::Event::Error(String::from("Front ends cannot attach"), e.into()));
+        return
+    }
+    eprintln!("DAEMON TAKING REQUESTS ON PORT {port}");
+
+    let front_ends = Arc::clone(&daemon);
+    thread::spawn(move || front_ends.serve(listener));
+    let outbox_peers = Arc::clone(&peers);
+    let outbox_report = daemon.reporter();
+    thread::spawn(move || comms::outbox_thread(outbox_report, outbox_peers));
+
+    comms::request_handler_thread(daemon.reporter(), listen_port, peers);
+    save::clear_daemon();
+}
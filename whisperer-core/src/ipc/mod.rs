@@ -0,0 +1,459 @@
+//! Talking to the daemon, which keeps receiving messages while no window is open
+//!
+//! The daemon (see `daemon`) owns the listener and the peer store, and takes requests on a port of the
+//! loopback address. Where, and the token every request has to start with, is in the `DAEMON` file
+//! in the data directory.
+//!
+//! Requests are framed like the wire protocol (see `tcp::write_raw_frame`), with the kind of request
+//! as command byte. The payload is the token followed by the fields of the request, each prefixed with
+//! its length as a big-endian u32. Every request is answered with one `RESPONSE` frame: a 0 and the
+//! result, or a 1 and the reason it failed. An `Attach` request keeps the connection open afterwards,
+//! and the daemon sends an `Update` frame on it for every peer it has and every change after that.
+pub mod daemon;
+
+use crate::{comms, msg, save, peers::PeerStore, tcp::{self, file, vector as vect}};
+use std::{
+    net::{Ipv4Addr, TcpStream},
+    path::PathBuf,
+    sync::Arc,
+    thread,
+    time::Duration
+};
+
+/// The length of the token every request starts with
+pub const TOKEN_SIZE: usize = 32;
+/// The command byte of the frame that answers a request
+const RESPONSE: u8 = 0;
+/// How long either end waits for the other. Adding a peer waits for the peer as well.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Something a front end asks the daemon to do, peers are named by address
+pub enum Request {
+    /// Check that the daemon runs
+    Ping,
+    /// Keep the connection open and send every change on it as an `Update`
+    Attach,
+    /// Check that a host runs Whisperer and agree on a key with it, answered with the new peer
+    Add {host: String, port: u16, alias: Option<String>},
+    /// Send a message, answered with its status once it got as far as it could.
+    /// A message that is already in the history is sent again.
+    Send(String, msg::Message),
+    /// Offer a file by its path, progress is reported to attached front ends
+    SendFile(String, PathBuf),
+    Alias(String, Option<String>),
+    Verified(String, bool),
+    ForgetIdentity(String),
+    ClearHistory(String),
+    Remove(String),
+    /// Accept or decline a file offer, by the ID of its transfer
+    AnswerFile(u64, bool),
+    Muted(String, bool),
+    /// Mark every message from a peer as read
+    MarkRead(String),
+    /// Change the passphrase the data is sealed with, see `save::set_passphrase`
+    Passphrase {current: Option<String>, new: Option<String>},
+    /// Bring in chat histories read from an archive, see `PeerStore::merge`, answered with how many messages were added
+    Import(Vec<msg::ChatHistory>)
+}
+impl Request {
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::Ping => 1,
+            Self::Attach => 2,
+            Self::Add {..} => 3,
+            Self::Send(..) => 4,
+            Self::SendFile(..) => 5,
+            Self::Alias(..) => 6,
+            Self::Verified(..) => 7,
+            Self::ForgetIdentity(_) => 8,
+            Self::ClearHistory(_) => 9,
+            Self::Remove(_) => 10,
+            Self::AnswerFile(..) => 11,
+            Self::Muted(..) => 12,
+            Self::MarkRead(_) => 13,
+            Self::Passphrase {..} => 14,
+            Self::Import(_) => 15
+        }
+    }
+    /// The fields of the request, without the token
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf: Vec<u8> = Vec::new();
+        match self {
+            Self::Ping | Self::Attach => (),
+            Self::Add {host, port, alias} => {
+                put(&mut buf, host.as_bytes());
+                put(&mut buf, &port.to_be_bytes());
+                put_optional(&mut buf, alias);
+            },
+            Self::Send(address, msg) => {
+                put(&mut buf, address.as_bytes());
+                put_message(&mut buf, msg);
+            },
+            Self::SendFile(address, path) => {
+                put(&mut buf, address.as_bytes());
+                put(&mut buf, path.to_string_lossy().as_bytes());
+            },
+            Self::Alias(address, alias) => {
+                put(&mut buf, address.as_bytes());
+                put_optional(&mut buf, alias);
+            },
+            Self::Verified(address, flag) | Self::Muted(address, flag) => {
+                put(&mut buf, address.as_bytes());
+                put(&mut buf, &[*flag as u8]);
+            },
+            Self::ForgetIdentity(address) | Self::ClearHistory(address) | Self::Remove(address) | Self::MarkRead(address) => put(&mut buf, address.as_bytes()),
+            Self::AnswerFile(id, accept) => {
+                put(&mut buf, &id.to_be_bytes());
+                put(&mut buf, &[*accept as u8]);
+            },
+            Self::Passphrase {current, new} => {
+                put_optional(&mut buf, current);
+                put_optional(&mut buf, new);
+            },
+            Self::Import(histories) => put(&mut buf, &save::format::to_bytes(histories))
+        }
+        buf
+    }
+    /// Read the fields of a request with the command byte `code`, `None` if they do not match
+    pub fn from_bytes(code: u8, bytes: &[u8]) -> Option<Self> {
+        let mut fields = Fields(bytes);
+        let request = match code {
+            1 => Self::Ping,
+            2 => Self::Attach,
+            3 => Self::Add {host: fields.string()?, port: u16::from_be_bytes(fields.next()?.try_into().ok()?), alias: fields.optional()?},
+            4 => Self::Send(fields.string()?, fields.message()?),
+            5 => Self::SendFile(fields.string()?, PathBuf::from(fields.string()?)),
+            6 => Self::Alias(fields.string()?, fields.optional()?),
+            7 => Self::Verified(fields.string()?, fields.flag()?),
+            8 => Self::ForgetIdentity(fields.string()?),
+            9 => Self::ClearHistory(fields.string()?),
+            10 => Self::Remove(fields.string()?),
+            11 => Self::AnswerFile(fields.number()?, fields.flag()?),
+            12 => Self::Muted(fields.string()?, fields.flag()?),
+            13 => Self::MarkRead(fields.string()?),
+            14 => Self::Passphrase {current: fields.optional()?, new: fields.optional()?},
+            15 => Self::Import(save::format::from_bytes(fields.next()?)?),
+            _ => return None
+        };
+        fields.0.is_empty().then_some(request)
+    }
+}
+
+/// Something that changed in the daemon, sent to every attached front end. Like the records of the
+/// journal (see `save::journal::Record`), only a new peer or a change to its details carries its whole
+/// history, so an update stays small however long the chat gets.
+pub enum Update {
+    /// A peer was added or changed, with its whole history
+    Peer(msg::ChatHistory),
+    /// A message was added to the history of a peer, with the unread count after it
+    Message {address: String, unread: usize, msg: msg::Message},
+    /// The status of every message a peer has left, for status changes, reads and cleared histories
+    History {address: String, unread: usize, statuses: Vec<(u64, msg::MessageStatus)>},
+    /// A peer was removed, by address
+    Removed(String),
+    /// See `comms::Event`
+    IdentityMismatch(String),
+    /// A file is offered, answer it with `Request::AnswerFile`
+    FileOffer(msg::Transfer),
+    FileProgress(msg::Transfer),
+    /// Something failed, with what was being done when it did and why
+    Error(String, String)
+}
+impl Update {
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::Peer(_) => 20,
+            Self::Removed(_) => 21,
+            Self::IdentityMismatch(_) => 22,
+            Self::FileOffer(_) => 23,
+            Self::FileProgress(_) => 24,
+            Self::Error(..) => 25,
+            Self::Message {..} => 26,
+            Self::History {..} => 27
+        }
+    }
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf: Vec<u8> = Vec::new();
+        match self {
+            Self::Peer(history) => put(&mut buf, &save::format::to_bytes(std::slice::from_ref(history))),
+            Self::Message {address, unread, msg} => {
+                put(&mut buf, address.as_bytes());
+                put(&mut buf, &(*unread as u64).to_be_bytes());
+                put_message(&mut buf, msg);
+            },
+            Self::History {address, unread, statuses} => {
+                put(&mut buf, address.as_bytes());
+                put(&mut buf, &(*unread as u64).to_be_bytes());
+                put_statuses(&mut buf, statuses);
+            },
+            Self::Removed(address) | Self::IdentityMismatch(address) => put(&mut buf, address.as_bytes()),
+            Self::FileOffer(transfer) | Self::FileProgress(transfer) => put_transfer(&mut buf, transfer),
+            Self::Error(context, reason) => {
+                put(&mut buf, context.as_bytes());
+                put(&mut buf, reason.as_bytes());
+            }
+        }
+        buf
+    }
+    pub fn from_bytes(code: u8, bytes: &[u8]) -> Option<Self> {
+        let mut fields = Fields(bytes);
+        let update = match code {
+            20 => Self::Peer(save::format::from_bytes(fields.next()?)?.pop()?),
+            21 => Self::Removed(fields.string()?),
+            22 => Self::IdentityMismatch(fields.string()?),
+            23 => Self::FileOffer(fields.transfer()?),
+            24 => Self::FileProgress(fields.transfer()?),
+            25 => Self::Error(fields.string()?, fields.string()?),
+            26 => Self::Message {address: fields.string()?, unread: fields.unread()?, msg: fields.message()?},
+            27 => Self::History {address: fields.string()?, unread: fields.unread()?, statuses: fields.statuses()?},
+            _ => return None
+        };
+        fields.0.is_empty().then_some(update)
+    }
+}
+
+/// Check that the daemon runs and takes requests
+pub fn running() -> bool {
+    request(&Request::Ping).is_ok()
+}
+
+/// Send `request` to the daemon and wait for its result
+pub fn request(request: &Request) -> Result<Vec<u8>, tcp::Error> {
+    let (port, token) = save::get_daemon().ok_or(tcp::Error::Daemon(String::from("The daemon is not running")))?;
+    request_at(port, &token, request)
+}
+
+/// Send `request` to the daemon taking requests on `port` with `token`
+pub fn request_at(port: u16, token: &[u8], request: &Request) -> Result<Vec<u8>, tcp::Error> {
+    open(port, token, request).map(|(_, result)| result)
+}
+
+/// Ask the daemon to add a peer, see `Request::Add`
+pub fn add_peer(host: String, port: u16, alias: Option<String>) -> Result<msg::Recipient, tcp::Error> {
+    let recipient = request(&Request::Add {host, port, alias})?;
+    save::format::from_bytes(&recipient).and_then(|mut histories| histories.pop())
+        .map(|history| history.peer())
+        .ok_or(tcp::Error::Protocol("Daemon did not answer with a peer"))
+}
+
+/// Ask the daemon to send a message, see `Request::Send`
+pub fn send_message(address: String, msg: msg::Message) -> Result<msg::MessageStatus, tcp::Error> {
+    match request(&Request::Send(address, msg))?.as_slice() {
+        [status] => Ok(msg::MessageStatus::from(*status)),
+        _ => Err(tcp::Error::Protocol("Daemon did not answer with a message status"))
+    }
+}
+
+/// Ask the daemon to bring in chat histories, see `Request::Import`
+pub fn import(histories: Vec<msg::ChatHistory>) -> Result<usize, tcp::Error> {
+    let added = request(&Request::Import(histories))?;
+    let added: [u8; 8] = added.try_into().map_err(|_| tcp::Error::Protocol("Daemon did not answer with a count"))?;
+    Ok(u64::from_be_bytes(added) as usize)
+}
+
+/// Attach to the daemon and keep `mirror` in sync with its peer store.
+/// Events are passed on to `report`, and file offers answered with `comms::FileOffer::answer` are sent back.
+/// Offers that are dropped without an answer are left to another front end, or to time out in the daemon.
+pub fn attach(mirror: Arc<PeerStore>, report: comms::Reporter) -> Result<(), tcp::Error> {
+    let (port, token) = save::get_daemon().ok_or(tcp::Error::Daemon(String::from("The daemon is not running")))?;
+    attach_at(port, token, mirror, report)
+}
+
+/// Attach to the daemon taking requests on `port` with `token`, see `attach`
+pub fn attach_at(port: u16, token: Vec<u8>, mirror: Arc<PeerStore>, report: comms::Reporter) -> Result<(), tcp::Error> {
+    let (mut stream, _) = open(port, &token, &Request::Attach)?;
+    stream.set_read_timeout(None)?;
+
+    thread::spawn(move || loop {
+        let update = tcp::read_raw_frame(&mut stream).and_then(|(code, payload)| {
+            Update::from_bytes(code, &payload).ok_or(tcp::Error::Protocol("Daemon sent an unknown update"))
+        });
+        match update {
+            Ok(Update::Peer(history)) => mirror.replace(history),
+            Ok(Update::Message {address, unread, msg}) => mirror.mirror_msg(&address, unread, msg),
+            Ok(Update::History {address, unread, statuses}) => mirror.mirror_history(&address, unread, &statuses),
+            Ok(Update::Removed(address)) => {mirror.remove(&address);},
+            Ok(Update::IdentityMismatch(address)) => report(comms::Event::IdentityMismatch(address)),
+            Ok(Update::FileOffer(transfer)) => {
+                let (offer, decision) = comms::FileOffer::new(transfer.clone());
+                report(comms::Event::FileOffer(offer));
+
+                let token = token.clone();
+                let report = Arc::clone(&report);
+                thread::spawn(move || {
+                    let Ok(accept) = decision.recv_timeout(file::OFFER_TIMEOUT) else { return };
+                    if let Err(e) = request_at(port, &token, &Request::AnswerFile(transfer.id(), accept)) {
+                        report(comms::Event::Error(format!("Could not answer the offer of {}", transfer.name()), e));
+                    }
+                });
+            },
+            Ok(Update::FileProgress(transfer)) => report(comms::Event::FileProgress(transfer)),
+            Ok(Update::Error(context, reason)) => report(comms::Event::Error(context, tcp::Error::Daemon(reason))),
+            Err(e) => {
+                eprintln!("LOST THE DAEMON: {e}");
+                report(comms::Event::Error(String::from("Lost the connection to the daemon, restart to reconnect"), e));
+                return
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Send `request` and read the result, leaving the connection open
+fn open(port: u16, token: &[u8], request: &Request) -> Result<(TcpStream, Vec<u8>), tcp::Error> {
+    let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    tcp::write_raw_frame(&mut stream, request.code(), &[token, &request.to_bytes()].concat())?;
+    let result = match tcp::read_raw_frame(&mut stream)? {
+        (RESPONSE, response) => match response.split_first() {
+            Some((0, result)) => Ok(result.to_vec()),
+            Some((_, reason)) => Err(tcp::Error::Daemon(vect::bytes_to_string(reason))),
+            None => Err(tcp::Error::Protocol("Daemon sent an empty response"))
+        },
+        _ => Err(tcp::Error::Protocol("Daemon did not send a response"))
+    }?;
+    Ok((stream, result))
+}
+
+/// Answer a request with its result
+fn respond(stream: &mut TcpStream, result: Result<Vec<u8>, String>) -> Result<(), tcp::Error> {
+    let response = match result {
+        Ok(result) => [[0u8].as_slice(), &result].concat(),
+        Err(reason) => [[1u8].as_slice(), reason.as_bytes()].concat()
+    };
+    tcp::write_raw_frame(stream, RESPONSE, &response)
+}
+
+fn put(buf: &mut Vec<u8>, field: &[u8]) {
+    buf.extend_from_slice(&(field.len() as u32).to_be_bytes());
+    buf.extend_from_slice(field);
+}
+
+/// An optional string, like an alias, is a flag saying whether there is one, and the string
+fn put_optional(buf: &mut Vec<u8>, string: &Option<String>) {
+    put(buf, &[string.is_some() as u8]);
+    put(buf, string.as_deref().unwrap_or_default().as_bytes());
+}
+
+fn put_message(buf: &mut Vec<u8>, msg: &msg::Message) {
+    put(buf, &msg.id().to_be_bytes());
+    put(buf, msg.author().as_bytes());
+    put(buf, msg.content().as_bytes());
+    put(buf, &msg.sent_at().to_be_bytes());
+    put(buf, &msg.received_at().to_be_bytes());
+    put(buf, &[msg.status().code()]);
+}
+
+/// The statuses of messages are a single field, with the ID of every message followed by its status byte
+fn put_statuses(buf: &mut Vec<u8>, statuses: &[(u64, msg::MessageStatus)]) {
+    let statuses: Vec<u8> = statuses.iter().flat_map(|(id, status)| [id.to_be_bytes().as_slice(), &[status.code()]].concat()).collect();
+    put(buf, &statuses);
+}
+
+/// A transfer's state is a code, followed by the reason for `Failed` and nothing for the others
+fn put_transfer(buf: &mut Vec<u8>, transfer: &msg::Transfer) {
+    put(buf, &transfer.id().to_be_bytes());
+    put(buf, transfer.peer().as_bytes());
+    put(buf, transfer.name().as_bytes());
+    put(buf, &transfer.size().to_be_bytes());
+    put(buf, &transfer.done().to_be_bytes());
+    put(buf, &[transfer.incoming() as u8]);
+    let (state, reason) = match transfer.state() {
+        msg::TransferState::Waiting => (0u8, String::new()),
+        msg::TransferState::Running => (1, String::new()),
+        msg::TransferState::Done => (2, String::new()),
+        msg::TransferState::Declined => (3, String::new()),
+        msg::TransferState::Failed(reason) => (4, reason)
+    };
+    put(buf, &[state]);
+    put(buf, reason.as_bytes());
+    put(buf, transfer.path().map(|path| path.to_string_lossy().to_string()).unwrap_or_default().as_bytes());
+}
+
+/// The length-prefixed fields of a payload, in order
+struct Fields<'a>(&'a [u8]);
+impl<'a> Iterator for Fields<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (length, rest) = self.0.split_at_checked(4)?;
+        let length = u32::from_be_bytes(length.try_into().ok()?) as usize;
+        let (field, rest) = rest.split_at_checked(length)?;
+        self.0 = rest;
+        Some(field)
+    }
+}
+impl Fields<'_> {
+    fn string(&mut self) -> Option<String> {
+        String::from_utf8(self.next()?.to_vec()).ok()
+    }
+    fn number(&mut self) -> Option<u64> {
+        Some(u64::from_be_bytes(self.next()?.try_into().ok()?))
+    }
+    fn unread(&mut self) -> Option<usize> {
+        usize::try_from(self.number()?).ok()
+    }
+    fn flag(&mut self) -> Option<bool> {
+        match self.next()? {
+            [flag] => Some(*flag != 0),
+            _ => None
+        }
+    }
+    /// Read an optional string made by `put_optional`, `Some(None)` if there is none
+    fn optional(&mut self) -> Option<Option<String>> {
+        let set = self.flag()?;
+        let string = self.string()?;
+        Some(set.then_some(string))
+    }
+    fn message(&mut self) -> Option<msg::Message> {
+        let id = self.number()?;
+        let author = self.string()?;
+        let content = self.string()?;
+        let mut msg = msg::Message::with_details(id, author, content, self.number()?, self.number()?);
+        match self.next()? {
+            [status] => msg.set_status(msg::MessageStatus::from(*status)),
+            _ => return None
+        }
+        Some(msg)
+    }
+    fn statuses(&mut self) -> Option<Vec<(u64, msg::MessageStatus)>> {
+        let statuses = self.next()?;
+        if statuses.len() % 9 != 0 {
+            return None
+        }
+        Some(statuses.chunks_exact(9).map(|status| {
+            (u64::from_be_bytes(status[..8].try_into().unwrap()), msg::MessageStatus::from(status[8]))
+        }).collect())
+    }
+    fn transfer(&mut self) -> Option<msg::Transfer> {
+        let id = self.number()?;
+        let peer = self.string()?;
+        let name = self.string()?;
+        let size = self.number()?;
+        let done = self.number()?;
+        let mut transfer = msg::Transfer::with_details(id, peer, name, size, self.flag()?);
+        transfer.set_done(done);
+
+        let state = self.next()?;
+        let reason = self.string()?;
+        transfer.set_state(match state {
+            [0] => msg::TransferState::Waiting,
+            [1] => msg::TransferState::Running,
+            [2] => msg::TransferState::Done,
+            [3] => msg::TransferState::Declined,
+            [4] => msg::TransferState::Failed(reason),
+            _ => return None
+        });
+
+        let path = self.string()?;
+        if !path.is_empty() {
+            transfer.set_path(PathBuf::from(path));
+        }
+        Some(transfer)
+    }
+}
+
+#[cfg(test)]
+mod tests;
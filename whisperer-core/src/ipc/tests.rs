@@ -0,0 +1,203 @@
+#![cfg(test)]
+
+use super::*;
+use daemon::Daemon;
+use crate::peers::Change;
+use std::{net::TcpListener, sync::{mpsc, Mutex}, time::Duration};
+
+/// A daemon for `peers` on an ephemeral port, returns the port and token
+fn start(peers: Arc<PeerStore>) -> (u16, Vec<u8>) {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let token = vec![7u8; TOKEN_SIZE];
+
+    let daemon = Daemon::new(token.clone(), peers, Arc::new(|_| ()));
+    thread::spawn(move || daemon.serve(listener));
+    (port, token)
+}
+
+#[test]
+fn requests_survive_encoding() {
+    let mut msg = msg::Message::with_details(42, String::from("You"), String::from("hi\nthere"), 1000, 2000);
+    msg.set_status(msg::MessageStatus::Queued);
+    let request = Request::Send(String::from("10.0.0.2"), msg);
+
+    match Request::from_bytes(request.code(), &request.to_bytes()) {
+        Some(Request::Send(host, msg)) => {
+            assert_eq!(host, "10.0.0.2");
+            assert_eq!((msg.id(), msg.author(), msg.content()), (42, String::from("You"), String::from("hi\nthere")));
+            assert_eq!((msg.sent_at(), msg.received_at(), msg.status()), (1000, 2000, msg::MessageStatus::Queued));
+        },
+        _ => panic!("send request did not survive")
+    }
+
+    let request = Request::Add {host: String::from("laptop.local"), port: 9999, alias: None};
+    assert!(matches!(
+        Request::from_bytes(request.code(), &request.to_bytes()),
+        Some(Request::Add {host, port: 9999, alias: None}) if host == "laptop.local"
+    ));
+    let request = Request::Alias(String::from("10.0.0.2"), Some(String::new()));
+    assert!(matches!(Request::from_bytes(request.code(), &request.to_bytes()), Some(Request::Alias(_, Some(alias))) if alias.is_empty()));
+
+    let request = Request::Passphrase {current: None, new: Some(String::from("correct horse"))};
+    assert!(matches!(
+        Request::from_bytes(request.code(), &request.to_bytes()),
+        Some(Request::Passphrase {current: None, new: Some(new)}) if new == "correct horse"
+    ));
+
+    let request = Request::Import(vec![msg::ChatHistory::new(msg::Recipient::from("10.0.0.2"))]);
+    assert!(matches!(
+        Request::from_bytes(request.code(), &request.to_bytes()),
+        Some(Request::Import(histories)) if histories.len() == 1 && histories[0].peer().ip() == "10.0.0.2"
+    ));
+
+    let mut bytes = Request::Remove(String::from("10.0.0.2")).to_bytes();
+    bytes.push(0);
+    assert!(Request::from_bytes(10, &bytes).is_none(), "trailing bytes are rejected");
+    assert!(Request::from_bytes(10, &bytes[..3]).is_none(), "cut off fields are rejected");
+    assert!(Request::from_bytes(99, &[]).is_none());
+}
+
+#[test]
+fn updates_survive_encoding() {
+    let mut transfer = msg::Transfer::new(String::from("10.0.0.2"), String::from("notes.txt"), 300, true);
+    transfer.set_done(120);
+    transfer.set_state(msg::TransferState::Failed(String::from("Connection reset")));
+    let update = Update::FileProgress(transfer.clone());
+
+    match Update::from_bytes(update.code(), &update.to_bytes()) {
+        Some(Update::FileProgress(decoded)) => {
+            assert_eq!((decoded.id(), decoded.peer(), decoded.name()), (transfer.id(), transfer.peer(), transfer.name()));
+            assert_eq!((decoded.size(), decoded.done(), decoded.incoming()), (300, 120, true));
+            assert!(decoded.state() == msg::TransferState::Failed(String::from("Connection reset")));
+            assert!(decoded.path().is_none());
+        },
+        _ => panic!("progress update did not survive")
+    }
+
+    let mut peer = msg::Recipient::from("10.0.0.2");
+    peer.set_alias(Some(String::from("desk")));
+    peer.set_muted(true);
+    let mut history = msg::ChatHistory::new(peer);
+    let mut pending = msg::Message::new(String::from("You"), String::from("still sending"));
+    pending.set_status(msg::MessageStatus::Pending);
+    history.push_msg(pending.clone());
+    history.push_msg(msg::Message::new(String::from("10.0.0.2"), String::from("unread")));
+    history.set_unread(1);
+
+    let update = Update::Peer(history);
+    match Update::from_bytes(update.code(), &update.to_bytes()) {
+        Some(Update::Peer(decoded)) => {
+            assert_eq!(decoded.peer().alias().as_deref(), Some("desk"));
+            assert!(decoded.peer().muted());
+            assert_eq!((decoded.history().len(), decoded.unread()), (2, 1));
+            let msg = decoded.find_msg(pending.id()).unwrap();
+            assert_eq!(msg.status(), msg::MessageStatus::Pending, "only loading from disk fails pending messages");
+        },
+        _ => panic!("peer update did not survive")
+    }
+
+    let update = Update::Message {address: String::from("10.0.0.2:9000"), unread: 3, msg: pending.clone()};
+    match Update::from_bytes(update.code(), &update.to_bytes()) {
+        Some(Update::Message {address, unread, msg}) => {
+            assert_eq!((address.as_str(), unread), ("10.0.0.2:9000", 3));
+            assert_eq!((msg.id(), msg.content(), msg.status()), (pending.id(), pending.content(), msg::MessageStatus::Pending));
+        },
+        _ => panic!("message update did not survive")
+    }
+
+    let statuses = vec![(1, msg::MessageStatus::Delivered), (u64::MAX, msg::MessageStatus::Failed)];
+    let update = Update::History {address: String::from("10.0.0.2"), unread: 0, statuses: statuses.clone()};
+    match Update::from_bytes(update.code(), &update.to_bytes()) {
+        Some(Update::History {address, unread, statuses: decoded}) => assert_eq!((address.as_str(), unread, decoded), ("10.0.0.2", 0, statuses)),
+        _ => panic!("history update did not survive")
+    }
+    let mut cut = update.to_bytes();
+    cut.truncate(cut.len() - 1);
+    let at = cut.len() - 17 - 4;
+    cut[at..at + 4].copy_from_slice(&17u32.to_be_bytes());
+    assert!(Update::from_bytes(update.code(), &cut).is_none(), "statuses have to be whole");
+}
+
+#[test]
+fn requests_need_the_token() {
+    let (port, token) = start(Arc::new(PeerStore::default()));
+
+    assert!(request_at(port, &token, &Request::Ping).is_ok());
+    assert!(matches!(request_at(port, &[8u8; TOKEN_SIZE], &Request::Ping), Err(tcp::Error::Daemon(_))));
+    assert!(matches!(request_at(port, &[], &Request::Ping), Err(tcp::Error::Daemon(_))));
+}
+
+#[test]
+fn attached_front_ends_mirror_the_daemon() {
+    let peers = Arc::new(PeerStore::default());
+    peers.add(msg::Recipient::from("10.0.0.2"));
+    let (port, token) = start(Arc::clone(&peers));
+
+    let mirror = Arc::new(PeerStore::default());
+    let (changed, changes) = mpsc::channel::<Change>();
+    let changed = Mutex::new(changed);
+    mirror.watch(move |change| changed.lock().unwrap().send(change).unwrap_or(()));
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let reported = Arc::clone(&errors);
+    attach_at(port, token.clone(), Arc::clone(&mirror), Arc::new(move |event| {
+        if let comms::Event::Error(context, _) = event {
+            reported.lock().unwrap().push(context);
+        }
+    })).unwrap();
+
+    let wait = || changes.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(matches!(wait(), Change::Peer(address) if address == "10.0.0.2"), "attaching sends every peer");
+
+    let msg = msg::Message::new(String::from("10.0.0.2"), String::from("hello"));
+    peers.push_msg("10.0.0.2", msg.clone());
+    assert!(matches!(wait(), Change::Message(_, id) if id == msg.id()), "new messages are announced on the mirror");
+    assert_eq!(mirror.history("10.0.0.2").unwrap().unread(), 1);
+
+    let mut ours = msg::Message::new(String::from("You"), String::from("hi back"));
+    ours.set_status(msg::MessageStatus::Pending);
+    peers.push_msg("10.0.0.2", ours.clone());
+    wait();
+    peers.set_status("10.0.0.2", ours.id(), msg::MessageStatus::Delivered);
+    peers.mark_read("10.0.0.2");
+    assert!(matches!(wait(), Change::History(_)));
+    assert!(matches!(wait(), Change::History(_)));
+    let mirrored = mirror.history("10.0.0.2").unwrap();
+    assert_eq!(mirrored.find_msg(ours.id()).map(|msg| msg.status()), Some(msg::MessageStatus::Delivered));
+    assert_eq!(mirrored.unread(), 0);
+    peers.clear_history("10.0.0.2");
+    wait();
+    assert!(mirror.history("10.0.0.2").unwrap().history().is_empty());
+
+    request_at(port, &token, &Request::Alias(String::from("10.0.0.2"), Some(String::from("desk")))).unwrap();
+    wait();
+    assert_eq!(mirror.alias("10.0.0.2").as_deref(), Some("desk"));
+    assert!(request_at(port, &token, &Request::Alias(String::from("10.0.0.9"), None)).is_err());
+
+    request_at(port, &token, &Request::Remove(String::from("10.0.0.2"))).unwrap();
+    assert!(matches!(wait(), Change::Removed(address) if address == "10.0.0.2"));
+    assert!(!peers.contains("10.0.0.2"));
+    assert!(errors.lock().unwrap().is_empty());
+}
+
+#[test]
+fn a_stalled_front_end_does_not_hold_up_the_others() {
+    let peers = Arc::new(PeerStore::default());
+    peers.add(msg::Recipient::from("10.0.0.2"));
+    // far more than the socket buffers take, so writing it to a front end that does not read blocks
+    peers.push_msg("10.0.0.2", msg::Message::new(String::from("10.0.0.2"), "x".repeat(16 * 1024 * 1024)));
+    let (port, token) = start(Arc::clone(&peers));
+    let (_stalled, _) = open(port, &token, &Request::Attach).unwrap();
+
+    let mirror = Arc::new(PeerStore::default());
+    let (changed, changes) = mpsc::channel::<Change>();
+    let changed = Mutex::new(changed);
+    mirror.watch(move |change| changed.lock().unwrap().send(change).unwrap_or(()));
+    attach_at(port, token, Arc::clone(&mirror), Arc::new(|_| ())).unwrap();
+
+    let wait = || changes.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(matches!(wait(), Change::Peer(_)), "attaching does not wait for the other front end");
+    let msg = msg::Message::new(String::from("10.0.0.2"), String::from("hello"));
+    peers.push_msg("10.0.0.2", msg.clone());
+    assert!(matches!(wait(), Change::Message(_, id) if id == msg.id()), "changes do not wait for the other front end");
+}
@@ -0,0 +1,181 @@
+//! Khyernet Encryption Module
+//!
+//! Messages are sealed with ChaCha20-Poly1305. Every call to `encrypt` picks a fresh random
+//! nonce and prepends it to the ciphertext, so the output is `nonce || ciphertext || tag`.
+//!
+//! Private keys are agreed on with X25519 and run through HKDF-SHA256, so the key never
+//! crosses the wire. Every install also has a long-term Ed25519 identity that signs the
+//! X25519 public keys it sends, so the other end can tell who it is talking to.
+//!
+//! Keys for data kept on disk are derived from a passphrase with Argon2id, which needs a lot of
+//! memory for every guess and so makes guessing the passphrase slow.
+#![allow(unused)]
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Nonce
+};
+use x25519_dalek::PublicKey;
+use ed25519_dalek::{Signature, Signer, Verifier, VerifyingKey};
+use hkdf::Hkdf;
+use sha2::{Digest, Sha256};
+
+pub use x25519_dalek::StaticSecret as Secret;
+pub use ed25519_dalek::SigningKey as Identity;
+
+/// The size of a private key in bytes
+pub const KEY_SIZE: usize = 32;
+/// The size of the nonce prepended to every ciphertext
+pub const NONCE_SIZE: usize = 12;
+/// The size of an X25519 public key in bytes
+pub const PUBLIC_KEY_SIZE: usize = 32;
+/// The size of the public half of an identity in bytes
+pub const IDENTITY_SIZE: usize = 32;
+/// The size of a signature made with an identity in bytes
+pub const SIGNATURE_SIZE: usize = 64;
+/// The size of a public key signed with `sign_key`
+pub const BUNDLE_SIZE: usize = IDENTITY_SIZE + PUBLIC_KEY_SIZE + SIGNATURE_SIZE;
+
+/// The size of the salt a key is derived from a passphrase with
+pub const SALT_SIZE: usize = 16;
+
+/// Returned when a key has the wrong size or a ciphertext fails authentication
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Error;
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Ciphertext failed authentication")
+    }
+}
+impl std::error::Error for Error {}
+
+/// Generate a new X25519 secret
+pub fn generate_secret() -> Secret {
+    Secret::random_from_rng(OsRng)
+}
+
+/// The public half of `secret`, safe to send in the clear
+pub fn public_key(secret: &Secret) -> Vec<u8> {
+    PublicKey::from(secret).as_bytes().to_vec()
+}
+
+/// Combine our `secret` with the other end's public key into a private key.
+///
+/// `initiator` and `responder` are the public keys of the end that started the exchange and
+/// the end that answered it. Both ends must pass them in the same order to derive the same key.
+pub fn agree(secret: &Secret, their_public: &[u8], initiator: &[u8], responder: &[u8]) -> Result<Vec<u8>, Error> {
+    let their_public: [u8; PUBLIC_KEY_SIZE] = their_public.try_into().map_err(|_| Error)?;
+    let shared = secret.diffie_hellman(&PublicKey::from(their_public));
+    if !shared.was_contributory() {
+        return Err(Error);
+    }
+
+    let info = [b"whisperer private key".as_slice(), initiator, responder].concat();
+    let mut key = vec![0u8; KEY_SIZE];
+    Hkdf::<Sha256>::new(None, shared.as_bytes())
+        .expand(&info, &mut key)
+        .map_err(|_| Error)?;
+
+    Ok(key)
+}
+
+pub fn encrypt<T, K>(bytes: T, key: K) -> Result<Vec<u8>, Error>
+where
+    T: Into<Vec<u8>>,
+    K: Into<Vec<u8>>
+{
+    let bytes: Vec<u8> = bytes.into();
+    let key: Vec<u8> = key.into();
+
+    let cipher = ChaCha20Poly1305::new_from_slice(&key).map_err(|_| Error)?;
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let sealed = cipher.encrypt(&nonce, bytes.as_slice()).map_err(|_| Error)?;
+
+    Ok([nonce.as_slice(), &sealed].concat())
+}
+
+pub fn decrypt<T, K>(bytes: T, key: K) -> Result<Vec<u8>, Error>
+where
+    T: Into<Vec<u8>>,
+    K: Into<Vec<u8>>
+{
+    let bytes: Vec<u8> = bytes.into();
+    let key: Vec<u8> = key.into();
+
+    if bytes.len() < NONCE_SIZE {
+        return Err(Error);
+    }
+    let (nonce, sealed) = bytes.split_at(NONCE_SIZE);
+
+    let cipher = ChaCha20Poly1305::new_from_slice(&key).map_err(|_| Error)?;
+    cipher.decrypt(Nonce::from_slice(nonce), sealed).map_err(|_| Error)
+}
+
+/// Derive a private key from `passphrase` with Argon2id, using `memory` KiB and `passes` passes over it.
+/// The same passphrase, salt and costs always give the same key.
+pub fn passphrase_key(passphrase: &str, salt: &[u8], memory: u32, passes: u32) -> Result<Vec<u8>, Error> {
+    let params = argon2::Params::new(memory, passes, 1, Some(KEY_SIZE)).map_err(|_| Error)?;
+    let argon = argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+
+    let mut key = vec![0u8; KEY_SIZE];
+    argon.hash_password_into(passphrase.as_bytes(), salt, &mut key).map_err(|_| Error)?;
+    Ok(key)
+}
+
+/// Generate a new long-term identity
+pub fn generate_identity() -> Identity {
+    Identity::generate(&mut OsRng)
+}
+
+/// Restore an identity from the bytes returned by `Identity::to_bytes`
+pub fn identity_from_bytes(bytes: &[u8]) -> Option<Identity> {
+    let bytes: [u8; IDENTITY_SIZE] = bytes.try_into().ok()?;
+    Some(Identity::from_bytes(&bytes))
+}
+
+/// The public half of `identity`, which is what the other end pins
+pub fn identity_public(identity: &Identity) -> Vec<u8> {
+    identity.verifying_key().as_bytes().to_vec()
+}
+
+/// Bundle `public_key` with our identity and a signature over it.
+///
+/// `context` is mixed into the signature so the bundle cannot be replayed elsewhere, usually
+/// the other end's public key.
+pub fn sign_key(identity: &Identity, public_key: &[u8], context: &[u8]) -> Vec<u8> {
+    let signature = identity.sign(&[public_key, context].concat());
+    [identity_public(identity).as_slice(), public_key, &signature.to_bytes()].concat()
+}
+
+/// Check a bundle made by `sign_key`, returns the identity and public key inside of it.
+pub fn verify_key(bundle: &[u8], context: &[u8]) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    if bundle.len() != BUNDLE_SIZE {
+        return Err(Error);
+    }
+    let (identity, rest) = bundle.split_at(IDENTITY_SIZE);
+    let (public_key, signature) = rest.split_at(PUBLIC_KEY_SIZE);
+
+    let verifying_key = VerifyingKey::try_from(identity).map_err(|_| Error)?;
+    let signature = Signature::from_slice(signature).map_err(|_| Error)?;
+    verifying_key.verify(&[public_key, context].concat(), &signature).map_err(|_| Error)?;
+
+    Ok((identity.to_vec(), public_key.to_vec()))
+}
+
+/// A safety number both ends can compare out of band to check that neither identity was
+/// swapped. The order of the two identities does not matter.
+pub fn fingerprint(ours: &[u8], theirs: &[u8]) -> String {
+    let (first, second) = if ours <= theirs {(ours, theirs)} else {(theirs, ours)};
+    let digest = Sha256::new()
+        .chain_update(b"whisperer safety number")
+        .chain_update(first)
+        .chain_update(second)
+        .finalize();
+
+    digest.chunks(5).take(6).map(|chunk| {
+        let number = chunk.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+        format!("{:05}", number % 100000)
+    }).collect::<Vec<String>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,83 @@
+#![cfg(test)]
+
+use super::*;
+use crate::tcp::vector as vect;
+
+#[test]
+fn round_trip() {
+    let key = vect::rand_byte_vector(KEY_SIZE);
+    let sealed = encrypt("i love lan parties", key.clone()).unwrap();
+    assert_eq!(decrypt(sealed, key).unwrap(), b"i love lan parties");
+}
+
+#[test]
+fn rejects_tampering() {
+    let key = vect::rand_byte_vector(KEY_SIZE);
+    let mut sealed = encrypt("i love lan parties", key.clone()).unwrap();
+    let last = sealed.len() - 1;
+    sealed[last] ^= 1;
+    assert_eq!(decrypt(sealed, key), Err(Error));
+}
+
+#[test]
+fn rejects_wrong_key() {
+    let sealed = encrypt("i love lan parties", vect::rand_byte_vector(KEY_SIZE)).unwrap();
+    assert_eq!(decrypt(sealed, vect::rand_byte_vector(KEY_SIZE)), Err(Error));
+}
+
+#[test]
+fn key_agreement() {
+    let ours = generate_secret();
+    let theirs = generate_secret();
+    let (our_public, their_public) = (public_key(&ours), public_key(&theirs));
+
+    let our_key = agree(&ours, &their_public, &our_public, &their_public).unwrap();
+    let their_key = agree(&theirs, &our_public, &our_public, &their_public).unwrap();
+    assert_eq!(our_key, their_key);
+    assert_eq!(our_key.len(), KEY_SIZE);
+}
+
+#[test]
+fn rejects_low_order_points() {
+    let ours = generate_secret();
+    let zero = [0u8; PUBLIC_KEY_SIZE];
+    assert_eq!(agree(&ours, &zero, &public_key(&ours), &zero), Err(Error));
+}
+
+#[test]
+fn signed_keys() {
+    let identity = generate_identity();
+    let public_key = public_key(&generate_secret());
+    let bundle = sign_key(&identity, &public_key, b"context");
+
+    let (signer, key) = verify_key(&bundle, b"context").unwrap();
+    assert_eq!(signer, identity_public(&identity));
+    assert_eq!(key, public_key);
+
+    assert_eq!(verify_key(&bundle, b"other context"), Err(Error));
+    let mut forged = bundle.clone();
+    forged[IDENTITY_SIZE] ^= 1;
+    assert_eq!(verify_key(&forged, b"context"), Err(Error));
+}
+
+#[test]
+fn fingerprints_match_on_both_ends() {
+    let ours = identity_public(&generate_identity());
+    let theirs = identity_public(&generate_identity());
+
+    let fingerprint = fingerprint(&ours, &theirs);
+    assert_eq!(fingerprint, super::fingerprint(&theirs, &ours));
+    assert_eq!(fingerprint.split(' ').count(), 6);
+    assert_ne!(fingerprint, super::fingerprint(&ours, &identity_public(&generate_identity())));
+}
+
+#[test]
+fn passphrase_keys() {
+    let salt = vect::rand_byte_vector(SALT_SIZE);
+    let key = passphrase_key("correct horse", &salt, 64, 1).unwrap();
+    assert_eq!(key.len(), KEY_SIZE);
+    assert_eq!(passphrase_key("correct horse", &salt, 64, 1).unwrap(), key);
+    assert_ne!(passphrase_key("correct horsf", &salt, 64, 1).unwrap(), key);
+    assert_ne!(passphrase_key("correct horse", &vect::rand_byte_vector(SALT_SIZE), 64, 1).unwrap(), key);
+    assert_eq!(passphrase_key("correct horse", &salt, 0, 1), Err(Error), "costs argon2 refuses are errors");
+}
@@ -0,0 +1,15 @@
+//! The protocol, crypto, peer store and persistence behind Whisperer, without any user interface
+//!
+//! The network threads in `comms` report to whatever front end started them through a `comms::Reporter`,
+//! and keep everything they learn about peers in a shared `peers::PeerStore`.
+//! `ipc` runs them in a daemon that front ends attach to, so messages keep arriving while none is open.
+pub mod kem;
+pub mod tcp;
+pub mod msg;
+pub mod comms;
+pub mod save;
+pub mod discovery;
+pub mod peers;
+pub mod ipc;
+pub mod notify;
+pub mod export;
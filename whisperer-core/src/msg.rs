@@ -0,0 +1,491 @@
+#![allow(unused)]
+use std::path::PathBuf;
+use chrono::TimeZone;
+
+#[derive(Clone, PartialEq)]
+pub struct Recipient {
+    ip: String,
+    port: u16,
+    alias: Option<String>,
+    private_key: Option<Vec<u8>>,
+    identity: Option<Vec<u8>>,
+    verified: bool,
+    key_changed: bool,
+    muted: bool
+}
+impl Recipient {
+    pub fn set_alias(&mut self, alias: Option<String>) {
+        self.alias = alias;
+    }
+    pub fn full_string(&self) -> String {
+        match &self.alias {
+            None => self.address(),
+            Some(a) => format!("{} ({})", a, self.address())
+        }
+    }
+    /// The host with the port added if it is not the default one, like `[fe80::1%eth0]:9999`.
+    /// No two peers have the same address, so it is what the peer store knows them by.
+    pub fn address(&self) -> String {
+        address(&self.ip, self.port)
+    }
+    pub fn alias(&self) -> Option<String> {
+        self.alias.clone()
+    }
+    pub fn private_key(&self) -> Option<Vec<u8>> {
+        self.private_key.clone()
+    }
+    pub fn set_private_key(&mut self, key: Vec<u8>) {
+        self.private_key = Some(key);
+    }
+    pub fn ip(&self) -> String {
+        self.ip.clone()
+    }
+    /// The port this peer listens on
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+    pub fn set_port(&mut self, port: u16) {
+        self.port = port;
+    }
+    /// The identity pinned on first contact, `None` if this peer has not been pinned yet
+    pub fn identity(&self) -> Option<Vec<u8>> {
+        self.identity.clone()
+    }
+    pub fn set_identity(&mut self, identity: Option<Vec<u8>>) {
+        self.identity = identity;
+    }
+    /// Drop the pinned identity and the private key, so the next key exchange pins whatever
+    /// identity the other end presents. A verified peer is flagged as having changed keys.
+    pub fn forget_identity(&mut self) {
+        self.identity = None;
+        self.private_key = None;
+        self.key_changed |= self.verified;
+        self.verified = false;
+    }
+    /// Whether the safety number of this peer was checked out of band
+    pub fn verified(&self) -> bool {
+        self.verified
+    }
+    pub fn set_verified(&mut self, verified: bool) {
+        self.verified = verified;
+        if verified {
+            self.key_changed = false;
+        }
+    }
+    /// Whether this peer's identity changed after it was verified
+    pub fn key_changed(&self) -> bool {
+        self.key_changed
+    }
+    pub fn set_key_changed(&mut self, key_changed: bool) {
+        self.key_changed = key_changed;
+    }
+    /// Whether messages from this peer are kept quiet instead of showing a notification
+    pub fn muted(&self) -> bool {
+        self.muted
+    }
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+}
+
+impl From<String> for Recipient {
+    fn from(string: String) -> Recipient {
+        Recipient {
+            ip: string,
+            port: crate::tcp::DEFAULT_PORT,
+            alias: None,
+            private_key: None,
+            identity: None,
+            verified: false,
+            key_changed: false,
+            muted: false
+        }
+    }
+}
+impl From<&str> for Recipient {
+    fn from(string: &str) -> Recipient {
+        Recipient {
+            ip: string.to_string(),
+            port: crate::tcp::DEFAULT_PORT,
+            alias: None,
+            private_key: None,
+            identity: None,
+            verified: false,
+            key_changed: false,
+            muted: false
+        }
+    }
+}
+
+/// Whether `host` can be used as the address of a recipient: an IPv4 address, an IPv6 address
+/// (link-local ones with a `%` zone like `fe80::1%eth0`), or a host name like `laptop.local`
+pub fn is_valid_host(host: impl ToString) -> bool {
+    let host = host.to_string();
+    if host_ip(&host).is_some() {return true}
+    if host.is_empty() || host.len() > 253 {return false}
+    // a name made of digits and dots is a mistyped IPv4 address, not a host name
+    if host.chars().all(|c| c.is_ascii_digit() || c == '.') {return false}
+
+    host.split('.').all(|label| {
+        !label.is_empty() && label.len() <= 63
+            && !label.starts_with('-') && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    })
+}
+
+/// Split what was typed into the Add menu into a host and a port, like `laptop.local:9999` or
+/// `[fe80::1%eth0]:9999`. Without a port the default one is used. `None` if it is not a valid address.
+pub fn parse_address(address: &str) -> Option<(String, u16)> {
+    let address = address.trim();
+    let (host, port) = match address.strip_prefix('[') {
+        Some(rest) => {
+            let (host, port) = rest.split_once(']')?;
+            match port {
+                "" => (host, None),
+                port => (host, Some(port.strip_prefix(':')?))
+            }
+        },
+        // more than one colon is a bare IPv6 address
+        None => match address.split_once(':') {
+            Some((host, port)) if !port.contains(':') => (host, Some(port)),
+            _ => (address, None)
+        }
+    };
+    let port = match port {
+        Some(port) => port.parse::<u16>().ok().filter(|port| *port != 0)?,
+        None => crate::tcp::DEFAULT_PORT
+    };
+
+    is_valid_host(host).then(|| (host.to_string(), port))
+}
+
+/// `host` with `port` added if it is not the default one, the way `parse_address` reads it back
+pub fn address(host: &str, port: u16) -> String {
+    match (port, host_ip(host)) {
+        (crate::tcp::DEFAULT_PORT, _) => host.to_string(),
+        (port, Some(std::net::IpAddr::V6(_))) => format!("[{host}]:{port}"),
+        (port, _) => format!("{host}:{port}")
+    }
+}
+
+/// The IP address `host` is written as, without its zone. `None` if `host` is a host name.
+pub fn host_ip(host: &str) -> Option<std::net::IpAddr> {
+    match host.split_once('%') {
+        Some((ip, zone)) if !zone.is_empty() => ip.parse::<std::net::Ipv6Addr>().ok().map(std::net::IpAddr::V6),
+        Some(_) => None,
+        None => host.parse().ok()
+    }
+}
+
+/// The current time in milliseconds since the unix epoch
+pub fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn local_time(millis: u64) -> Option<chrono::DateTime<chrono::Local>> {
+    if millis == 0 {return None}
+    chrono::Local.timestamp_millis_opt(millis as i64).single()
+}
+/// The local time of day of a timestamp, like `14:05`
+pub fn format_time(millis: u64) -> Option<String> {
+    local_time(millis).map(|time| time.format("%H:%M").to_string())
+}
+/// The local date and time of a timestamp, like `2026-10-18 14:05:09`
+pub fn format_date_time(millis: u64) -> Option<String> {
+    local_time(millis).map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+/// The local day of a timestamp, like `Sunday, 18 October 2026`
+pub fn format_day(millis: u64) -> Option<String> {
+    local_time(millis).map(|time| time.format("%A, %-d %B %Y").to_string())
+}
+
+/// How far an outgoing message got
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MessageStatus {
+    /// Still being sent
+    Pending,
+    /// The other end took the message but has not shown it yet
+    Sent,
+    /// The other end decrypted and showed the message
+    #[default]
+    Delivered,
+    /// The message could not be sent, it can be retried
+    Failed,
+    /// The other end is offline, the message waits in the outbox until it comes back
+    Queued
+}
+impl MessageStatus {
+    /// The byte this status is stored as
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::Pending => 0,
+            Self::Sent => 1,
+            Self::Delivered => 2,
+            Self::Failed => 3,
+            Self::Queued => 4
+        }
+    }
+    /// The word this status is shown and exported as
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Pending => "sending",
+            Self::Sent => "sent",
+            Self::Delivered => "delivered",
+            Self::Failed => "failed",
+            Self::Queued => "queued"
+        }
+    }
+    /// The status shown as `name`, `None` if there is none
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::Pending, Self::Sent, Self::Delivered, Self::Failed, Self::Queued].into_iter().find(|status| status.name() == name)
+    }
+}
+impl From<u8> for MessageStatus {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::Pending,
+            1 => Self::Sent,
+            2 => Self::Delivered,
+            4 => Self::Queued,
+            _ => Self::Failed
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Message {
+    id: u64,
+    author: String,
+    content: String,
+    sent_at: u64,
+    received_at: u64,
+    status: MessageStatus
+}
+impl Message {
+    /// A new message written now, with a random ID
+    pub fn new(author: String, content: String) -> Self {
+        let now = now();
+        Self {
+            id: rand::random(),
+            author,
+            content,
+            sent_at: now,
+            received_at: now,
+            status: MessageStatus::default()
+        }
+    }
+    /// Rebuild a message that was stored or sent earlier
+    pub fn with_details(id: u64, author: String, content: String, sent_at: u64, received_at: u64) -> Self {
+        Self {id, author, content, sent_at, received_at, status: MessageStatus::default()}
+    }
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+    pub fn author(&self) -> String {
+        self.author.clone()
+    }
+    pub fn content(&self) -> String {
+        self.content.clone()
+    }
+    /// When the sender wrote the message, in milliseconds since the unix epoch. 0 if unknown.
+    pub fn sent_at(&self) -> u64 {
+        self.sent_at
+    }
+    /// When this end received the message, in milliseconds since the unix epoch. 0 if unknown.
+    pub fn received_at(&self) -> u64 {
+        self.received_at
+    }
+    /// How far the message got, only meaningful for messages written by "You"
+    pub fn status(&self) -> MessageStatus {
+        self.status
+    }
+    pub fn set_status(&mut self, status: MessageStatus) {
+        self.status = status;
+    }
+    pub fn clean_nulls(&mut self) {
+        self.content.retain(|c| c != '\0');
+    }
+    /// The plaintext sent on the wire: ID, sender timestamp and UTF-8 content
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [
+            self.id.to_be_bytes().as_slice(),
+            &self.sent_at.to_be_bytes(),
+            self.content.as_bytes()
+        ].concat()
+    }
+    /// Read a message made by `to_bytes`, stamping it as received now
+    pub fn from_bytes(author: String, bytes: &[u8]) -> Option<Self> {
+        let (id, rest) = bytes.split_at_checked(8)?;
+        let (sent_at, content) = rest.split_at_checked(8)?;
+
+        Some(Self {
+            id: u64::from_be_bytes(id.try_into().ok()?),
+            author,
+            content: crate::tcp::vector::bytes_to_string(content),
+            sent_at: u64::from_be_bytes(sent_at.try_into().ok()?),
+            received_at: now(),
+            status: MessageStatus::Delivered
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct ChatHistory {
+    peer: Recipient,
+    history: Vec<Message>,
+    unread: usize
+}
+impl ChatHistory {
+    pub fn new(peer: Recipient) -> Self {
+        Self {
+            peer,
+            history: Vec::new(),
+            unread: 0
+        }
+    }
+    pub fn push_msg(&mut self, msg: Message) {
+        self.history.push(msg)
+    }
+    pub fn find_msg(&self, id: u64) -> Option<Message> {
+        self.history.iter().find(|msg| msg.id() == id).cloned()
+    }
+    /// Change the status of the message with `id`, returns whether there was such a message
+    pub fn set_status(&mut self, id: u64, status: MessageStatus) -> bool {
+        match self.history.iter_mut().find(|msg| msg.id() == id) {
+            Some(msg) => {
+                msg.set_status(status);
+                true
+            },
+            None => false
+        }
+    }
+    /// The ID and status of every message, in order
+    pub fn statuses(&self) -> Vec<(u64, MessageStatus)> {
+        self.history.iter().map(|msg| (msg.id(), msg.status())).collect()
+    }
+    /// Keep only the messages in `statuses`, and give them those statuses
+    pub fn set_statuses(&mut self, statuses: &[(u64, MessageStatus)]) {
+        self.history.retain(|msg| statuses.iter().any(|(id, _)| *id == msg.id()));
+        for (id, status) in statuses {
+            self.set_status(*id, *status);
+        }
+    }
+    pub fn peer(&self) -> Recipient {
+        self.peer.clone()
+    }
+    pub fn history(&self) -> Vec<Message> {
+        self.history.clone()
+    }
+    pub fn update_peer(&mut self, new: Recipient) {
+        self.peer = new
+    }
+    /// Move this chat to a new host of its peer, the messages the peer wrote move along with it
+    pub fn set_host(&mut self, host: String) {
+        for msg in self.history.iter_mut().filter(|msg| msg.author == self.peer.ip) {
+            msg.author = host.clone();
+        }
+        self.peer.ip = host;
+    }
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+        self.unread = 0;
+    }
+    /// How many messages from the peer arrived since its chat was last looked at
+    pub fn unread(&self) -> usize {
+        self.unread
+    }
+    pub fn set_unread(&mut self, unread: usize) {
+        self.unread = unread;
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub enum TransferState {
+    Waiting, Running, Done, Declined, Failed(String)
+}
+
+/// A file being sent to or received from a peer
+#[derive(Clone)]
+pub struct Transfer {
+    id: u64,
+    peer: String,
+    name: String,
+    size: u64,
+    done: u64,
+    incoming: bool,
+    state: TransferState,
+    path: Option<PathBuf>
+}
+impl Transfer {
+    pub fn new(peer: String, name: String, size: u64, incoming: bool) -> Self {
+        Self {
+            id: rand::random(),
+            peer,
+            name,
+            size,
+            done: 0,
+            incoming,
+            state: TransferState::Waiting,
+            path: None
+        }
+    }
+    /// A transfer that was started somewhere else, like in the daemon
+    pub fn with_details(id: u64, peer: String, name: String, size: u64, incoming: bool) -> Self {
+        Self {id, ..Self::new(peer, name, size, incoming)}
+    }
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+    pub fn peer(&self) -> String {
+        self.peer.clone()
+    }
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+    pub fn done(&self) -> u64 {
+        self.done
+    }
+    pub fn set_done(&mut self, done: u64) {
+        self.done = done;
+    }
+    pub fn incoming(&self) -> bool {
+        self.incoming
+    }
+    pub fn state(&self) -> TransferState {
+        self.state.clone()
+    }
+    pub fn set_state(&mut self, state: TransferState) {
+        self.state = state;
+    }
+    /// Where a received file was saved
+    pub fn path(&self) -> Option<PathBuf> {
+        self.path.clone()
+    }
+    pub fn set_path(&mut self, path: PathBuf) {
+        self.path = Some(path);
+    }
+    pub fn finished(&self) -> bool {
+        !matches!(self.state, TransferState::Waiting | TransferState::Running)
+    }
+}
+
+/// Format a size in bytes for humans, like `1.5 MiB`
+pub fn format_size(size: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = size as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{size} {}", units[unit]),
+        _ => format!("{size:.1} {}", units[unit])
+    }
+}
\ No newline at end of file
@@ -0,0 +1,84 @@
+//! Desktop notifications for incoming messages
+//!
+//! On Linux they are sent to the freedesktop notification service over the D-Bus session bus.
+//! Other platforms have no notifier yet, so `Notifier::session` returns `None` there.
+use crate::peers::PeerStore;
+
+/// The most characters of a message that are shown in a notification
+pub const BODY_LENGTH: usize = 200;
+
+/// The summary and body of the notification for the message with `id` from the peer with `address`.
+/// `None` if it should stay quiet: for messages we wrote, and for messages from muted peers.
+pub fn message_notification(peers: &PeerStore, address: &str, id: u64) -> Option<(String, String)> {
+    let peer = peers.get(address).filter(|peer| !peer.muted())?;
+    let msg = peers.find_msg(address, id).filter(|msg| msg.author() != "You")?;
+
+    let content = msg.content();
+    let mut body: String = content.chars().take(BODY_LENGTH).collect();
+    if content.chars().count() > BODY_LENGTH {
+        body.push('…');
+    }
+    Some((peer.alias().unwrap_or(peer.address()), escape(&body)))
+}
+
+/// Escape the characters notification services read as markup in a body
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// A connection to the notification service of the desktop
+#[derive(Clone)]
+pub struct Notifier {
+    #[cfg(target_os = "linux")]
+    connection: zbus::blocking::Connection
+}
+
+#[cfg(target_os = "linux")]
+impl Notifier {
+    /// Connect to the notification service on the session bus, `None` if there is no session bus
+    pub fn session() -> Option<Self> {
+        match zbus::blocking::Connection::session() {
+            Ok(connection) => Some(Self::new(connection)),
+            Err(e) => {
+                eprintln!("NO SESSION BUS: {e}");
+                None
+            }
+        }
+    }
+    /// Send notifications over `connection`, which has to reach `org.freedesktop.Notifications`
+    pub fn new(connection: zbus::blocking::Connection) -> Self {
+        Self {connection}
+    }
+    /// Show a notification, returns whether the notification service took it
+    pub fn notify(&self, summary: &str, body: &str) -> bool {
+        let hints = std::collections::HashMap::from([("category", zbus::zvariant::Value::from("im.received"))]);
+        let result = self.connection.call_method(
+            Some("org.freedesktop.Notifications"),
+            "/org/freedesktop/Notifications",
+            Some("org.freedesktop.Notifications"),
+            "Notify",
+            // app name, ID of the notification to replace, icon, summary, body, actions, hints, timeout
+            &("Whisperer", 0u32, "", summary, body, Vec::<&str>::new(), hints, -1i32)
+        );
+        match result {
+            Ok(_) => true,
+            Err(e) => {
+                eprintln!("COULD NOT NOTIFY: {e}");
+                false
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl Notifier {
+    pub fn session() -> Option<Self> {
+        None
+    }
+    pub fn notify(&self, _summary: &str, _body: &str) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests;
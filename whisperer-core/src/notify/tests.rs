@@ -0,0 +1,91 @@
+#![cfg(test)]
+
+use super::*;
+use crate::msg::{Message, Recipient};
+
+#[test]
+fn only_unmuted_peers_notify() {
+    let store = PeerStore::default();
+    let mut peer = Recipient::from("10.0.0.2");
+    peer.set_alias(Some(String::from("desk")));
+    store.add(peer);
+
+    let theirs = Message::new(String::from("10.0.0.2"), String::from("<b>lunch</b> & coffee?"));
+    let ours = Message::new(String::from("You"), String::from("sure"));
+    store.push_msg("10.0.0.2", theirs.clone());
+    store.push_msg("10.0.0.2", ours.clone());
+
+    assert_eq!(
+        message_notification(&store, "10.0.0.2", theirs.id()),
+        Some((String::from("desk"), String::from("&lt;b&gt;lunch&lt;/b&gt; &amp; coffee?")))
+    );
+    assert_eq!(message_notification(&store, "10.0.0.2", ours.id()), None);
+
+    store.modify("10.0.0.2", |peer| peer.set_muted(true));
+    assert_eq!(message_notification(&store, "10.0.0.2", theirs.id()), None);
+}
+
+#[test]
+fn long_messages_are_cut_short() {
+    let store = PeerStore::default();
+    store.add(Recipient::from("10.0.0.2"));
+    let msg = Message::new(String::from("10.0.0.2"), "ä".repeat(BODY_LENGTH + 1));
+    store.push_msg("10.0.0.2", msg.clone());
+
+    let (summary, body) = message_notification(&store, "10.0.0.2", msg.id()).unwrap();
+    assert_eq!(summary, "10.0.0.2");
+    assert_eq!(body.chars().count(), BODY_LENGTH + 1);
+    assert!(body.ends_with('…'));
+}
+
+#[cfg(target_os = "linux")]
+mod bus {
+    use super::*;
+    use std::{collections::HashMap, os::unix::net::UnixStream, sync::{Arc, Mutex}, thread};
+    use zbus::{blocking::ConnectionBuilder, zvariant::Value, Guid};
+
+    /// Stands in for the notification service, and keeps what it was sent
+    struct MockNotifications {
+        shown: Arc<Mutex<Vec<(String, String, String)>>>
+    }
+    #[zbus::dbus_interface(name = "org.freedesktop.Notifications")]
+    impl MockNotifications {
+        #[allow(clippy::too_many_arguments)]
+        fn notify(
+            &self,
+            app_name: &str,
+            _replaces_id: u32,
+            _app_icon: &str,
+            summary: &str,
+            body: &str,
+            _actions: Vec<&str>,
+            _hints: HashMap<&str, Value<'_>>,
+            _expire_timeout: i32
+        ) -> u32 {
+            let mut shown = self.shown.lock().unwrap();
+            shown.push((app_name.to_string(), summary.to_string(), body.to_string()));
+            shown.len() as u32
+        }
+    }
+
+    #[test]
+    fn notifications_reach_the_service() {
+        let (service, client) = UnixStream::pair().unwrap();
+        let shown = Arc::new(Mutex::new(Vec::new()));
+
+        let mock = MockNotifications {shown: Arc::clone(&shown)};
+        let server = thread::spawn(move || {
+            ConnectionBuilder::unix_stream(service)
+                .server(&Guid::generate())
+                .p2p()
+                .serve_at("/org/freedesktop/Notifications", mock).unwrap()
+                .build().unwrap()
+        });
+        let connection = ConnectionBuilder::unix_stream(client).p2p().build().unwrap();
+        let _server = server.join().unwrap();
+
+        let notifier = Notifier::new(connection);
+        assert!(notifier.notify("desk", "lunch?"));
+        assert_eq!(*shown.lock().unwrap(), vec![(String::from("Whisperer"), String::from("desk"), String::from("lunch?"))]);
+    }
+}
@@ -0,0 +1,345 @@
+//! The peers this install knows about, each with its chat history
+//!
+//! A single `PeerStore` is shared through an `Arc` by the UI and the network threads. Peers are
+//! keyed by their address, the host and the port they listen on as `Recipient::address` writes them,
+//! so two instances on one computer are two peers. A peer that presents an identity pinned for
+//! another address is the same peer moving there. Everyone who wants to know when something
+//! changes registers a callback with `watch`.
+#![allow(unused)]
+use crate::{msg::{ChatHistory, Message, MessageStatus, Recipient}, tcp};
+use std::{collections::HashSet, sync::RwLock};
+
+/// What changed in a `PeerStore`, peers are named by address
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// A peer was added, or its details or address changed
+    Peer(String),
+    /// A peer was removed along with its history
+    Removed(String),
+    /// A message was added to the history of a peer
+    Message(String, u64),
+    /// Messages in the history of a peer changed, were cleared or were read
+    History(String)
+}
+
+/// A callback registered with `PeerStore::watch`
+type Watcher = Box<dyn Fn(Change) + Send + Sync>;
+
+#[derive(Default)]
+pub struct PeerStore {
+    peers: RwLock<Vec<ChatHistory>>,
+    watchers: RwLock<Vec<Watcher>>
+}
+impl PeerStore {
+    /// A store holding `histories`, every peer with its own history. Later duplicates of an address are dropped.
+    pub fn new(histories: Vec<ChatHistory>) -> Self {
+        let mut peers: Vec<ChatHistory> = Vec::new();
+        for history in histories {
+            if !peers.iter().any(|known| known.peer().address() == history.peer().address()) {
+                peers.push(history);
+            }
+        }
+        Self {peers: RwLock::new(peers), watchers: RwLock::default()}
+    }
+
+    /// Call `watcher` for every change from now on. It is called after the change is made, and may read the store.
+    pub fn watch(&self, watcher: impl Fn(Change) + Send + Sync + 'static) {
+        self.watchers.write().unwrap().push(Box::new(watcher));
+    }
+    fn notify(&self, change: Change) {
+        for watcher in self.watchers.read().unwrap().iter() {
+            watcher(change.clone());
+        }
+    }
+
+    /// Every peer, in the order they were added
+    pub fn peers(&self) -> Vec<Recipient> {
+        self.peers.read().unwrap().iter().map(|history| history.peer()).collect()
+    }
+    /// Every peer with its history, in the order they were added
+    pub fn histories(&self) -> Vec<ChatHistory> {
+        self.peers.read().unwrap().clone()
+    }
+    pub fn get(&self, address: &str) -> Option<Recipient> {
+        self.history(address).map(|history| history.peer())
+    }
+    pub fn history(&self, address: &str) -> Option<ChatHistory> {
+        self.peers.read().unwrap().iter().find(|history| history.peer().address() == address).cloned()
+    }
+    /// Every peer on `host`, whatever port it listens on
+    pub fn on_host(&self, host: &str) -> Vec<Recipient> {
+        self.peers().into_iter().filter(|peer| peer.ip() == host).collect()
+    }
+    /// The peer that has `identity` pinned
+    pub fn by_identity(&self, identity: &[u8]) -> Option<Recipient> {
+        self.peers().into_iter().find(|peer| peer.identity().is_some_and(|pinned| pinned == identity))
+    }
+    pub fn contains(&self, address: &str) -> bool {
+        self.get(address).is_some()
+    }
+    pub fn alias(&self, address: &str) -> Option<String> {
+        self.get(address).and_then(|peer| peer.alias())
+    }
+
+    /// Add `peer` with an empty history. If its identity is already pinned for another peer, that
+    /// peer moves to the new address and keeps its history. Returns false if the address is already known.
+    pub fn add(&self, peer: Recipient) -> bool {
+        let address = peer.address();
+        let mut peers = self.peers.write().unwrap();
+        if peers.iter().any(|history| history.peer().address() == address) {return false}
+        let moved = insert(&mut peers, peer);
+        drop(peers);
+
+        if let Some(old) = moved {
+            self.notify(Change::Removed(old));
+        }
+        self.notify(Change::Peer(address));
+        true
+    }
+
+    /// Store the `key` agreed with `host` listening on `port`, which presented `identity`.
+    /// Unknown addresses are added, unless the identity is pinned for another peer, which then moves there.
+    /// Nothing changes and this fails with `tcp::Error::IdentityMismatch` if the address has a different identity pinned.
+    pub fn store_key(&self, host: &str, port: u16, identity: Vec<u8>, key: Vec<u8>) -> Result<(), tcp::Error> {
+        let address = crate::msg::address(host, port);
+        let mut peers = self.peers.write().unwrap();
+        let mut moved: Option<String> = None;
+        match peers.iter_mut().find(|history| history.peer().address() == address) {
+            Some(history) => {
+                let mut peer = history.peer();
+                if peer.identity().is_some_and(|pinned| pinned != identity) {
+                    return Err(tcp::Error::IdentityMismatch)
+                }
+                peer.set_identity(Some(identity));
+                peer.set_private_key(key);
+                history.update_peer(peer);
+            },
+            None => {
+                let mut peer = Recipient::from(host);
+                peer.set_port(port);
+                peer.set_identity(Some(identity));
+                peer.set_private_key(key);
+                moved = insert(&mut peers, peer);
+            }
+        }
+        drop(peers);
+
+        if let Some(old) = moved {
+            self.notify(Change::Removed(old));
+        }
+        self.notify(Change::Peer(address));
+        Ok(())
+    }
+
+    /// Put `history` in place of whatever is stored for its peer, for stores that mirror another one.
+    /// Messages that were not there before are announced, unless the peer is new.
+    pub fn replace(&self, history: ChatHistory) {
+        let address = history.peer().address();
+        let mut peers = self.peers.write().unwrap();
+        let new_messages: Vec<u64> = match peers.iter_mut().find(|known| known.peer().address() == address) {
+            Some(known) => {
+                let new = history.history().iter()
+                    .filter(|msg| known.find_msg(msg.id()).is_none())
+                    .map(|msg| msg.id())
+                    .collect();
+                *known = history;
+                new
+            },
+            None => {
+                peers.push(history);
+                Vec::new()
+            }
+        };
+        drop(peers);
+
+        self.notify(Change::Peer(address.clone()));
+        for id in new_messages {
+            self.notify(Change::Message(address.clone(), id));
+        }
+    }
+
+    /// Add `msg` to the history of the peer with `address` and set how many messages are unread,
+    /// for stores that mirror another one. The message is announced unless it was there already.
+    pub fn mirror_msg(&self, address: &str, unread: usize, msg: Message) {
+        let id = msg.id();
+        let mut peers = self.peers.write().unwrap();
+        let Some(history) = peers.iter_mut().find(|history| history.peer().address() == address) else {return};
+        let new = history.find_msg(id).is_none();
+        if new {
+            history.push_msg(msg);
+        }
+        history.set_unread(unread);
+        drop(peers);
+
+        match new {
+            true => self.notify(Change::Message(address.to_string(), id)),
+            false => self.notify(Change::History(address.to_string()))
+        }
+    }
+    /// Keep only the messages of the peer with `address` that are in `statuses`, with those statuses,
+    /// and set how many messages are unread, for stores that mirror another one
+    pub fn mirror_history(&self, address: &str, unread: usize, statuses: &[(u64, MessageStatus)]) {
+        let mut peers = self.peers.write().unwrap();
+        let Some(history) = peers.iter_mut().find(|history| history.peer().address() == address) else {return};
+        history.set_statuses(statuses);
+        history.set_unread(unread);
+        drop(peers);
+
+        self.notify(Change::History(address.to_string()));
+    }
+
+    /// Bring in the messages of `history` that its peer does not have yet, by ID, and the peer itself if it is not known.
+    /// Known peers keep their details, and only take the alias of `history` if they have none. Messages go before
+    /// the first one sent after them, and are not counted as unread. Returns how many messages were added.
+    pub fn merge(&self, history: ChatHistory) -> usize {
+        let address = history.peer().address();
+        let mut peers = self.peers.write().unwrap();
+        let (at, mut changed) = match peers.iter().position(|known| known.peer().address() == address) {
+            Some(at) => (at, false),
+            None => {
+                peers.push(ChatHistory::new(history.peer()));
+                (peers.len() - 1, true)
+            }
+        };
+        let known = &mut peers[at];
+        let mut peer = known.peer();
+        if peer.alias().is_none() && history.peer().alias().is_some() {
+            peer.set_alias(history.peer().alias());
+            known.update_peer(peer);
+            changed = true;
+        }
+
+        let mut messages = known.history();
+        let mut ids: HashSet<u64> = messages.iter().map(|msg| msg.id()).collect();
+        let mut added: usize = 0;
+        for msg in history.history() {
+            if !ids.insert(msg.id()) {continue}
+            let at = match msg.sent_at() {
+                0 => messages.len(),
+                sent_at => messages.iter().position(|known| known.sent_at() > sent_at).unwrap_or(messages.len())
+            };
+            messages.insert(at, msg);
+            added += 1;
+        }
+        if added > 0 {
+            let unread = known.unread();
+            known.clear_history();
+            for msg in messages {
+                known.push_msg(msg);
+            }
+            known.set_unread(unread);
+        }
+        drop(peers);
+
+        if changed || added > 0 {
+            self.notify(Change::Peer(address));
+        }
+        added
+    }
+
+    /// Apply `change` to the peer with `address`, returns whether there was such a peer.
+    /// The change cannot move the peer to another address.
+    pub fn modify(&self, address: &str, change: impl FnOnce(&mut Recipient)) -> bool {
+        let mut peers = self.peers.write().unwrap();
+        let Some(history) = peers.iter_mut().find(|history| history.peer().address() == address) else {return false};
+        let mut peer = history.peer();
+        change(&mut peer);
+        if peer.address() != address {return false}
+        history.update_peer(peer);
+        drop(peers);
+
+        self.notify(Change::Peer(address.to_string()));
+        true
+    }
+
+    /// Remove the peer with `address` and its history
+    pub fn remove(&self, address: &str) -> bool {
+        let mut peers = self.peers.write().unwrap();
+        let before = peers.len();
+        peers.retain(|history| history.peer().address() != address);
+        let removed = peers.len() != before;
+        drop(peers);
+
+        if removed {
+            self.notify(Change::Removed(address.to_string()));
+        }
+        removed
+    }
+
+    /// Add `msg` to the history of the peer with `address`, messages not written by us count as unread.
+    /// Returns false if there is no such peer, or a message with the same ID is already there.
+    pub fn push_msg(&self, address: &str, msg: Message) -> bool {
+        let id = msg.id();
+        let mut peers = self.peers.write().unwrap();
+        let Some(history) = peers.iter_mut().find(|history| history.peer().address() == address) else {return false};
+        if history.find_msg(id).is_some() {return false}
+        if msg.author() != "You" {
+            history.set_unread(history.unread() + 1);
+        }
+        history.push_msg(msg);
+        drop(peers);
+
+        self.notify(Change::Message(address.to_string(), id));
+        true
+    }
+    pub fn find_msg(&self, address: &str, id: u64) -> Option<Message> {
+        self.history(address).and_then(|history| history.find_msg(id))
+    }
+    /// Change the status of the message with `id` sent to `address`, returns whether there was such a message
+    pub fn set_status(&self, address: &str, id: u64, status: MessageStatus) -> bool {
+        let mut peers = self.peers.write().unwrap();
+        let changed = peers.iter_mut()
+            .find(|history| history.peer().address() == address)
+            .is_some_and(|history| history.set_status(id, status));
+        drop(peers);
+
+        if changed {
+            self.notify(Change::History(address.to_string()));
+        }
+        changed
+    }
+    /// Mark every message from the peer with `address` as read
+    pub fn mark_read(&self, address: &str) {
+        let mut peers = self.peers.write().unwrap();
+        let Some(history) = peers.iter_mut().find(|history| history.peer().address() == address && history.unread() > 0) else {return};
+        history.set_unread(0);
+        drop(peers);
+
+        self.notify(Change::History(address.to_string()));
+    }
+    /// How many messages arrived from every peer together since their chats were last looked at
+    pub fn unread(&self) -> usize {
+        self.peers.read().unwrap().iter().map(|history| history.unread()).sum()
+    }
+    pub fn clear_history(&self, address: &str) {
+        let mut peers = self.peers.write().unwrap();
+        let Some(history) = peers.iter_mut().find(|history| history.peer().address() == address) else {return};
+        history.clear_history();
+        drop(peers);
+
+        self.notify(Change::History(address.to_string()));
+    }
+}
+
+/// Add `peer` to `peers`, or move the peer with the same identity to its address.
+/// Returns the address a peer was moved away from.
+fn insert(peers: &mut Vec<ChatHistory>, peer: Recipient) -> Option<String> {
+    let same = peer.identity().and_then(|identity| {
+        peers.iter_mut().find(|history| history.peer().identity().is_some_and(|pinned| pinned == identity))
+    });
+    match same {
+        Some(history) => {
+            let old = history.peer().address();
+            history.set_host(peer.ip());
+            history.update_peer(peer);
+            Some(old)
+        },
+        None => {
+            peers.push(ChatHistory::new(peer));
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
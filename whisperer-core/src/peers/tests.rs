@@ -0,0 +1,150 @@
+#![cfg(test)]
+
+use super::*;
+use std::sync::{Arc, Mutex};
+
+fn pinned(host: &str, identity: u8) -> Recipient {
+    let mut peer = Recipient::from(host);
+    peer.set_identity(Some(vec![identity; 32]));
+    peer
+}
+
+#[test]
+fn peers_keep_their_history() {
+    let store = PeerStore::default();
+    assert!(store.add(Recipient::from("10.0.0.2")));
+    assert!(!store.add(Recipient::from("10.0.0.2")));
+
+    let msg = Message::new(String::from("10.0.0.2"), String::from("hi"));
+    assert!(store.push_msg("10.0.0.2", msg.clone()));
+    assert!(!store.push_msg("10.0.0.2", msg.clone()), "duplicates are dropped");
+    assert!(!store.push_msg("10.0.0.3", msg.clone()), "unknown peers have no history");
+
+    assert!(store.modify("10.0.0.2", |peer| peer.set_alias(Some(String::from("desk")))));
+    assert_eq!(store.alias("10.0.0.2").as_deref(), Some("desk"));
+    assert_eq!(store.history("10.0.0.2").unwrap().history().len(), 1);
+
+    assert!(store.remove("10.0.0.2"));
+    assert!(store.history("10.0.0.2").is_none());
+}
+
+#[test]
+fn identities_move_between_hosts() {
+    let store = PeerStore::default();
+    store.add(pinned("10.0.0.2", 1));
+    store.push_msg("10.0.0.2", Message::new(String::from("10.0.0.2"), String::from("before")));
+
+    store.store_key("10.0.0.7", 9999, vec![1; 32], vec![2; 32]).unwrap();
+
+    assert!(!store.contains("10.0.0.2"));
+    let peer = store.get("10.0.0.7:9999").unwrap();
+    assert_eq!(peer.port(), 9999);
+    assert_eq!(peer.private_key(), Some(vec![2; 32]));
+    assert_eq!(store.history("10.0.0.7:9999").unwrap().history()[0].author(), "10.0.0.7");
+    assert_eq!(store.by_identity(&[1; 32]).map(|peer| peer.ip()).as_deref(), Some("10.0.0.7"));
+}
+
+#[test]
+fn peers_are_keyed_by_address() {
+    let store = PeerStore::default();
+    let mut other = Recipient::from("10.0.0.2");
+    other.set_port(9000);
+    assert!(store.add(Recipient::from("10.0.0.2")));
+    assert!(store.add(other.clone()));
+    assert!(!store.add(other));
+
+    store.push_msg("10.0.0.2:9000", Message::new(String::from("10.0.0.2"), String::from("hi")));
+    assert_eq!(store.history("10.0.0.2").unwrap().history().len(), 0);
+    assert_eq!(store.history("10.0.0.2:9000").unwrap().history().len(), 1);
+    assert_eq!(store.on_host("10.0.0.2").len(), 2);
+    assert!(!store.modify("10.0.0.2", |peer| peer.set_port(9000)), "peers cannot take another address");
+
+    store.store_key("10.0.0.2", 9000, vec![1; 32], vec![2; 32]).unwrap();
+    assert_eq!(store.get("10.0.0.2:9000").unwrap().private_key(), Some(vec![2; 32]));
+    assert_eq!(store.get("10.0.0.2").unwrap().private_key(), None);
+}
+
+#[test]
+fn pinned_identities_are_kept() {
+    let store = PeerStore::default();
+    store.add(pinned("10.0.0.2", 1));
+
+    assert!(matches!(store.store_key("10.0.0.2", tcp::DEFAULT_PORT, vec![3; 32], vec![2; 32]), Err(tcp::Error::IdentityMismatch)));
+    assert_eq!(store.get("10.0.0.2").unwrap().private_key(), None);
+    assert_eq!(store.peers().len(), 1);
+}
+
+#[test]
+fn watchers_hear_changes() {
+    let store = PeerStore::default();
+    let heard = Arc::new(Mutex::new(Vec::new()));
+    let log = Arc::clone(&heard);
+    store.watch(move |change| log.lock().unwrap().push(change));
+
+    let mut msg = Message::new(String::from("You"), String::from("hi"));
+    msg.set_status(MessageStatus::Pending);
+    store.add(Recipient::from("10.0.0.2"));
+    store.push_msg("10.0.0.2", msg.clone());
+    store.set_status("10.0.0.2", msg.id(), MessageStatus::Delivered);
+    store.remove("10.0.0.2");
+
+    assert_eq!(*heard.lock().unwrap(), vec![
+        Change::Peer(String::from("10.0.0.2")),
+        Change::Message(String::from("10.0.0.2"), msg.id()),
+        Change::History(String::from("10.0.0.2")),
+        Change::Removed(String::from("10.0.0.2"))
+    ]);
+}
+
+#[test]
+fn messages_from_peers_are_unread() {
+    let store = PeerStore::default();
+    store.add(Recipient::from("10.0.0.2"));
+    store.add(Recipient::from("10.0.0.3"));
+
+    store.push_msg("10.0.0.2", Message::new(String::from("10.0.0.2"), String::from("one")));
+    store.push_msg("10.0.0.2", Message::new(String::from("10.0.0.2"), String::from("two")));
+    store.push_msg("10.0.0.2", Message::new(String::from("You"), String::from("mine")));
+    store.push_msg("10.0.0.3", Message::new(String::from("10.0.0.3"), String::from("three")));
+    assert_eq!(store.history("10.0.0.2").unwrap().unread(), 2);
+    assert_eq!(store.unread(), 3);
+
+    store.mark_read("10.0.0.2");
+    assert_eq!(store.history("10.0.0.2").unwrap().unread(), 0);
+    store.clear_history("10.0.0.3");
+    assert_eq!(store.unread(), 0);
+}
+
+#[test]
+fn merged_histories_skip_what_is_there() {
+    let store = PeerStore::default();
+    let mut peer = Recipient::from("10.0.0.2");
+    peer.set_alias(Some(String::from("desk")));
+    store.add(peer);
+    store.push_msg("10.0.0.2", Message::with_details(1, String::from("10.0.0.2"), String::from("first"), 1000, 1000));
+    store.push_msg("10.0.0.2", Message::with_details(3, String::from("You"), String::from("third"), 3000, 3000));
+    let heard = Arc::new(Mutex::new(Vec::new()));
+    let log = Arc::clone(&heard);
+    store.watch(move |change| log.lock().unwrap().push(change));
+
+    let mut peer = Recipient::from("10.0.0.2");
+    peer.set_alias(Some(String::from("laptop")));
+    let mut archived = ChatHistory::new(peer);
+    archived.push_msg(Message::with_details(1, String::from("10.0.0.2"), String::from("first"), 1000, 1000));
+    archived.push_msg(Message::with_details(2, String::from("10.0.0.2"), String::from("second"), 2000, 2000));
+    archived.push_msg(Message::with_details(4, String::from("You"), String::from("fourth"), 4000, 4000));
+    assert_eq!(store.merge(archived.clone()), 2);
+    assert_eq!(store.merge(archived), 0, "merging twice adds nothing");
+
+    let history = store.history("10.0.0.2").unwrap();
+    let ids: Vec<u64> = history.history().iter().map(|msg| msg.id()).collect();
+    assert_eq!(ids, vec![1, 2, 3, 4], "merged messages are put in the order they were sent");
+    assert_eq!(history.unread(), 1, "merged messages are not unread");
+    assert_eq!(history.peer().alias(), Some(String::from("desk")), "known peers keep their alias");
+    assert_eq!(*heard.lock().unwrap(), vec![Change::Peer(String::from("10.0.0.2"))]);
+
+    let mut archived = ChatHistory::new(Recipient::from("10.0.0.3"));
+    archived.push_msg(Message::with_details(5, String::from("10.0.0.3"), String::from("hi"), 5000, 5000));
+    assert_eq!(store.merge(archived), 1);
+    assert_eq!(store.history("10.0.0.3").map(|history| history.history().len()), Some(1));
+}
@@ -0,0 +1,65 @@
+use std::{fmt, io, path::PathBuf};
+
+/// Why stored data could not be read or changed
+#[derive(Debug)]
+pub enum Error {
+    /// The data is sealed with another passphrase, or none was given
+    WrongPassphrase,
+    /// A file was written by a newer version, or is not in any format this version knows
+    Unreadable,
+    /// Reading or writing a file failed
+    Io(io::Error)
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongPassphrase => write!(f, "Wrong passphrase"),
+            Self::Unreadable => write!(f, "Saved data is in a format this version cannot read"),
+            Self::Io(e) => write!(f, "{e}")
+        }
+    }
+}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None
+        }
+    }
+}
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Something that went wrong while loading the stored data, and what was done about it
+#[derive(Debug)]
+pub enum Problem {
+    /// There is no place for data on this platform, so it is kept in this temporary directory
+    NoDataDirectory(PathBuf),
+    /// The data is sealed and its passphrase was not given, so nothing was loaded
+    Locked,
+    /// `file` was written by a newer version, so nothing was loaded and nothing is saved over it
+    Newer(PathBuf),
+    /// Nothing in `file` could be read. It was moved to `backup`, unless that failed.
+    Unreadable {file: PathBuf, backup: Option<PathBuf>},
+    /// `skipped` records in `file` could not be read, the rest was loaded. It was copied to `backup`, unless that failed.
+    Damaged {file: PathBuf, skipped: usize, backup: Option<PathBuf>}
+}
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kept = |backup: &Option<PathBuf>| match backup {
+            Some(backup) => format!("it was kept as {}", backup.to_string_lossy()),
+            None => String::from("it could not be backed up")
+        };
+        match self {
+            Self::NoDataDirectory(path) => write!(f, "There is no data directory on this system, data is kept in {} until it restarts", path.to_string_lossy()),
+            Self::Locked => write!(f, "Saved data is locked with a passphrase"),
+            Self::Newer(file) => write!(f, "{} was saved by a newer version of Whisperer, nothing will be saved over it until you update", file.to_string_lossy()),
+            Self::Unreadable {file, backup} => write!(f, "{} could not be read, {}", file.to_string_lossy(), kept(backup)),
+            Self::Damaged {file, skipped: 1, backup} => write!(f, "A damaged record in {} was skipped, {}", file.to_string_lossy(), kept(backup)),
+            Self::Damaged {file, skipped, backup} => write!(f, "{skipped} damaged records in {} were skipped, {}", file.to_string_lossy(), kept(backup))
+        }
+    }
+}
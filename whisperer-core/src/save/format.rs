@@ -0,0 +1,267 @@
+//! The versioned format of the `PEERS` file, which holds every peer with its chat history
+//!
+//! The file starts with `MAGIC` and the version of the format as a big-endian u16. Everything after
+//! that is made of entries: a tag byte, the length of the value as a big-endian u32, and the value.
+//! Numbers are big-endian, text is UTF-8, and records are entries themselves. Readers skip the tags
+//! they do not know, so later versions can add entries that older readers ignore. Changes that
+//! cannot be ignored need a new version, which older readers refuse instead of misreading it.
+//!
+//! At the top level, every peer is a `PEER` record with its details and a `peer::MESSAGE` record for every message.
+use crate::msg;
+
+/// The first bytes of every file in this format
+pub const MAGIC: &[u8; 4] = b"WHSP";
+/// The version of the format written by this build
+pub const VERSION: u16 = 1;
+
+/// A peer with its chat history
+const PEER: u8 = 1;
+
+/// The entries of a `PEER` record
+mod peer {
+    pub const HOST: u8 = 1;
+    pub const PORT: u8 = 2;
+    pub const ALIAS: u8 = 3;
+    pub const IDENTITY: u8 = 4;
+    pub const KEY: u8 = 5;
+    /// A byte of `VERIFIED`, `KEY_CHANGED` and `MUTED`
+    pub const FLAGS: u8 = 6;
+    pub const UNREAD: u8 = 7;
+    pub const MESSAGE: u8 = 8;
+
+    pub const VERIFIED: u8 = 1;
+    pub const KEY_CHANGED: u8 = 2;
+    pub const MUTED: u8 = 4;
+}
+
+/// The entries of a message record
+mod message {
+    pub const ID: u8 = 1;
+    /// `You` for messages we wrote, the host of the peer otherwise
+    pub const AUTHOR: u8 = 2;
+    pub const CONTENT: u8 = 3;
+    pub const SENT_AT: u8 = 4;
+    pub const RECEIVED_AT: u8 = 5;
+    pub const STATUS: u8 = 6;
+}
+
+/// Write every peer with its history in the current version of the format
+pub fn to_bytes(histories: &[msg::ChatHistory]) -> Vec<u8> {
+    let mut buf: Vec<u8> = MAGIC.to_vec();
+    buf.extend_from_slice(&VERSION.to_be_bytes());
+    for history in histories {
+        put(&mut buf, PEER, &peer_to_bytes(history));
+    }
+    buf
+}
+
+/// Read every peer with its history, `None` if `bytes` are not in this format, were cut short,
+/// or were written by a newer version
+pub fn from_bytes(bytes: &[u8]) -> Option<Vec<msg::ChatHistory>> {
+    let mut histories: Vec<msg::ChatHistory> = Vec::new();
+    let mut entries = Entries(body(bytes)?);
+    for (tag, value) in entries.by_ref() {
+        if tag == PEER {
+            histories.push(peer_from_bytes(value)?);
+        }
+    }
+    entries.0.is_empty().then_some(histories)
+}
+
+/// Read whatever can be read of `bytes`, along with how many peers and messages had to be skipped.
+/// A record that does not make sense is skipped, and so is what follows the point where a file was cut short.
+/// `None` if `bytes` are not in this format at all, or were written by a newer version.
+pub fn recover(bytes: &[u8]) -> Option<(Vec<msg::ChatHistory>, usize)> {
+    let mut histories: Vec<msg::ChatHistory> = Vec::new();
+    let mut skipped: usize = 0;
+    let mut entries = Entries(body(bytes)?);
+    for (tag, value) in entries.by_ref() {
+        if tag != PEER {continue}
+        match read_peer(value, false) {
+            Some((history, skipped_messages)) => {
+                histories.push(history);
+                skipped += skipped_messages;
+            },
+            None => skipped += 1
+        }
+    }
+    if !entries.0.is_empty() {
+        skipped += 1;
+    }
+    Some((histories, skipped))
+}
+
+/// Whether `bytes` are in this format, but written by a newer version that this one cannot read
+pub fn newer(bytes: &[u8]) -> bool {
+    bytes.strip_prefix(MAGIC)
+        .and_then(|rest| rest.get(..2))
+        .is_some_and(|version| u16::from_be_bytes([version[0], version[1]]) > VERSION)
+}
+
+/// The entries after the header, `None` if there is no header or it is from a newer version
+fn body(bytes: &[u8]) -> Option<&[u8]> {
+    let rest = bytes.strip_prefix(MAGIC)?;
+    let (version, rest) = rest.split_at_checked(2)?;
+    (u16::from_be_bytes(version.try_into().ok()?) <= VERSION).then_some(rest)
+}
+
+/// A `PEER` record of `history`, without its tag
+pub(super) fn peer_to_bytes(history: &msg::ChatHistory) -> Vec<u8> {
+    let peer = history.peer();
+    let mut buf: Vec<u8> = Vec::new();
+    put(&mut buf, peer::HOST, peer.ip().as_bytes());
+    put(&mut buf, peer::PORT, &peer.port().to_be_bytes());
+    if let Some(alias) = peer.alias() {
+        put(&mut buf, peer::ALIAS, alias.as_bytes());
+    }
+    if let Some(identity) = peer.identity() {
+        put(&mut buf, peer::IDENTITY, &identity);
+    }
+    if let Some(key) = peer.private_key() {
+        put(&mut buf, peer::KEY, &key);
+    }
+    put(&mut buf, peer::FLAGS, &[
+        if peer.verified() {peer::VERIFIED} else {0}
+        | if peer.key_changed() {peer::KEY_CHANGED} else {0}
+        | if peer.muted() {peer::MUTED} else {0}
+    ]);
+    put(&mut buf, peer::UNREAD, &(history.unread() as u64).to_be_bytes());
+
+    for msg in history.history().iter() {
+        put(&mut buf, peer::MESSAGE, &message_to_bytes(msg));
+    }
+    buf
+}
+
+/// A message record of `msg`, without its tag
+pub(super) fn message_to_bytes(msg: &msg::Message) -> Vec<u8> {
+    let mut buf: Vec<u8> = Vec::new();
+    put(&mut buf, message::ID, &msg.id().to_be_bytes());
+    put(&mut buf, message::AUTHOR, msg.author().as_bytes());
+    put(&mut buf, message::CONTENT, msg.content().as_bytes());
+    put(&mut buf, message::SENT_AT, &msg.sent_at().to_be_bytes());
+    put(&mut buf, message::RECEIVED_AT, &msg.received_at().to_be_bytes());
+    put(&mut buf, message::STATUS, &[msg.status().code()]);
+    buf
+}
+
+/// Read a `PEER` record, `None` if it has no host or an entry does not make sense
+pub(super) fn peer_from_bytes(bytes: &[u8]) -> Option<msg::ChatHistory> {
+    read_peer(bytes, true).map(|(history, _)| history)
+}
+
+/// Read a `PEER` record, `None` if it has no host or its details do not make sense.
+/// Unless `strict` is set, messages that do not make sense are skipped instead, and returned as a count
+/// along with the peer, and so is what follows the point where the record was cut short.
+fn read_peer(bytes: &[u8], strict: bool) -> Option<(msg::ChatHistory, usize)> {
+    let mut host: Option<String> = None;
+    let mut port: Option<u16> = None;
+    let mut alias: Option<String> = None;
+    let mut identity: Option<Vec<u8>> = None;
+    let mut key: Option<Vec<u8>> = None;
+    let mut flags: u8 = 0;
+    let mut unread: usize = 0;
+    let mut messages: Vec<msg::Message> = Vec::new();
+    let mut skipped: usize = 0;
+
+    let mut entries = Entries(bytes);
+    for (tag, value) in entries.by_ref() {
+        match tag {
+            peer::HOST => host = Some(text(value)?),
+            peer::PORT => port = Some(u16::from_be_bytes(value.try_into().ok()?)),
+            peer::ALIAS => alias = Some(text(value)?),
+            peer::IDENTITY => identity = Some(value.to_vec()),
+            peer::KEY => key = Some(value.to_vec()),
+            peer::FLAGS => flags = *value.first()?,
+            peer::UNREAD => unread = usize::try_from(number(value)?).ok()?,
+            peer::MESSAGE => match message_from_bytes(value) {
+                Some(msg) => messages.push(msg),
+                None if strict => return None,
+                None => skipped += 1
+            },
+            _ => ()
+        }
+    }
+    if !entries.0.is_empty() {
+        if strict {
+            return None
+        }
+        skipped += 1;
+    }
+
+    let mut peer = msg::Recipient::from(host?);
+    if let Some(port) = port { peer.set_port(port) }
+    peer.set_alias(alias);
+    peer.set_identity(identity);
+    if let Some(key) = key { peer.set_private_key(key) }
+    peer.set_verified(flags & peer::VERIFIED != 0 && peer.identity().is_some());
+    peer.set_key_changed(flags & peer::KEY_CHANGED != 0);
+    peer.set_muted(flags & peer::MUTED != 0);
+
+    let mut history = msg::ChatHistory::new(peer);
+    for msg in messages {
+        history.push_msg(msg);
+    }
+    history.set_unread(unread);
+    Some((history, skipped))
+}
+
+/// Read a message record, `None` if it has no ID or author, or an entry does not make sense
+pub(super) fn message_from_bytes(bytes: &[u8]) -> Option<msg::Message> {
+    let mut id: Option<u64> = None;
+    let mut author: Option<String> = None;
+    let mut content = String::new();
+    let mut sent_at: u64 = 0;
+    let mut received_at: u64 = 0;
+    let mut status = msg::MessageStatus::default();
+
+    let mut entries = Entries(bytes);
+    for (tag, value) in entries.by_ref() {
+        match tag {
+            message::ID => id = Some(number(value)?),
+            message::AUTHOR => author = Some(text(value)?),
+            message::CONTENT => content = text(value)?,
+            message::SENT_AT => sent_at = number(value)?,
+            message::RECEIVED_AT => received_at = number(value)?,
+            message::STATUS => status = msg::MessageStatus::from(*value.first()?),
+            _ => ()
+        }
+    }
+    if !entries.0.is_empty() {
+        return None
+    }
+
+    let mut msg = msg::Message::with_details(id?, author?, content, sent_at, received_at);
+    msg.set_status(status);
+    Some(msg)
+}
+
+/// Add an entry with `tag` and `value` to `buf`
+pub(super) fn put(buf: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    buf.push(tag);
+    buf.extend_from_slice(&(value.len() as u32).to_be_bytes());
+    buf.extend_from_slice(value);
+}
+
+pub(super) fn number(value: &[u8]) -> Option<u64> {
+    Some(u64::from_be_bytes(value.try_into().ok()?))
+}
+
+pub(super) fn text(value: &[u8]) -> Option<String> {
+    String::from_utf8(value.to_vec()).ok()
+}
+
+/// The entries of a record, in order. Whatever is left once it stops was cut short.
+pub(super) struct Entries<'a>(pub(super) &'a [u8]);
+impl<'a> Iterator for Entries<'a> {
+    type Item = (u8, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (&tag, rest) = self.0.split_first()?;
+        let (length, rest) = rest.split_at_checked(4)?;
+        let length = u32::from_be_bytes(length.try_into().ok()?) as usize;
+        let (value, rest) = rest.split_at_checked(length)?;
+        self.0 = rest;
+        Some((tag, value))
+    }
+}
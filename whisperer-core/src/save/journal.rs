@@ -0,0 +1,289 @@
+//! The journal of changes made since the `PEERS` file was last written
+//!
+//! Every change to the peer store is appended to the `JOURNAL` file the moment it is made, so nothing
+//! is lost if the process dies before the next full save. Loading applies the records to what `PEERS`
+//! holds, in order, and every full save starts a new journal. Records describe the state after a
+//! change rather than the change itself, so applying one that `PEERS` already has changes nothing.
+//!
+//! The file starts with `MAGIC` and the version of the journal as a big-endian u16, followed by a
+//! frame for every record: the length of the record as a big-endian u32, the first `CHECKSUM_SIZE`
+//! bytes of its SHA-256, a byte saying whether it is sealed with `lock::seal_record`, and the record.
+//! A frame that is cut short or does not match its checksum was torn while it was being written,
+//! so it is dropped along with everything after it. Intact records this version cannot read are skipped.
+//!
+//! A record is a single entry in the `format` of `PEERS`, see `Record` for what each tag holds.
+use super::{format::{self, Entries, put, number, text}, lock};
+use crate::{msg, peers::{Change, PeerStore}};
+use sha2::{Digest, Sha256};
+
+/// The first bytes of every journal
+pub const MAGIC: &[u8; 4] = b"WHSJ";
+/// The version of the journal written by this build
+pub const VERSION: u16 = 1;
+/// How many bytes of the SHA-256 of a record are kept to tell whether it is intact
+const CHECKSUM_SIZE: usize = 8;
+/// The size of a frame before its record: length, checksum and whether it is sealed
+const FRAME_HEADER_SIZE: usize = 4 + CHECKSUM_SIZE + 1;
+
+/// The tags of the records, and of the entries inside `MESSAGE` and `HISTORY` records
+mod tag {
+    pub const PEER: u8 = 1;
+    pub const MESSAGE: u8 = 2;
+    pub const HISTORY: u8 = 3;
+    /// A removed peer named by its host, see `Name::Host`
+    pub const REMOVED: u8 = 4;
+    pub const REMOVED_ADDRESS: u8 = 5;
+
+    /// The host of the peer, see `Name::Host`
+    pub const HOST: u8 = 1;
+    pub const UNREAD: u8 = 2;
+    pub const RECORD: u8 = 3;
+    /// The ID of a message followed by its status byte
+    pub const STATUS: u8 = 4;
+    pub const ADDRESS: u8 = 5;
+}
+
+/// How a record names the peer it is about
+#[derive(Clone, Debug, PartialEq)]
+pub enum Name {
+    /// The address the peer store knows the peer by
+    Address(String),
+    /// Only the host of the peer, which journals wrote before peers were keyed by address.
+    /// These records are about every peer on the host, as they were then.
+    Host(String)
+}
+impl Name {
+    fn is(&self, peer: &msg::Recipient) -> bool {
+        match self {
+            Self::Address(address) => peer.address() == *address,
+            Self::Host(host) => peer.ip() == *host
+        }
+    }
+}
+
+/// What a peer looked like after it changed
+#[derive(Clone)]
+pub enum Record {
+    /// A peer with its whole history, for new peers and changes to their details or keys.
+    /// It takes the place of the peer with the same address or identity.
+    Peer(msg::ChatHistory),
+    /// A message added to the history of a peer, with the unread count after it
+    Message {peer: Name, unread: usize, msg: msg::Message},
+    /// The status of every message a peer has left, for status changes, reads and cleared histories
+    History {peer: Name, unread: usize, statuses: Vec<(u64, msg::MessageStatus)>},
+    /// A peer was removed along with its history
+    Removed(Name)
+}
+impl Record {
+    /// The record for `change`, read from `peers` after it was made. `None` if the peer is gone by now.
+    pub fn for_change(peers: &PeerStore, change: &Change) -> Option<Self> {
+        Some(match change {
+            Change::Peer(address) => Self::Peer(peers.history(address)?),
+            Change::Removed(address) => Self::Removed(Name::Address(address.clone())),
+            Change::Message(address, id) => Self::Message {
+                peer: Name::Address(address.clone()),
+                unread: peers.history(address)?.unread(),
+                msg: peers.find_msg(address, *id)?
+            },
+            Change::History(address) => {
+                let history = peers.history(address)?;
+                Self::History {
+                    peer: Name::Address(address.clone()),
+                    unread: history.unread(),
+                    statuses: history.statuses()
+                }
+            }
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf: Vec<u8> = Vec::new();
+        match self {
+            Self::Peer(history) => put(&mut buf, tag::PEER, &format::peer_to_bytes(history)),
+            Self::Message {peer, unread, msg} => {
+                let mut record: Vec<u8> = Vec::new();
+                put_name(&mut record, peer);
+                put(&mut record, tag::UNREAD, &(*unread as u64).to_be_bytes());
+                put(&mut record, tag::RECORD, &format::message_to_bytes(msg));
+                put(&mut buf, tag::MESSAGE, &record);
+            },
+            Self::History {peer, unread, statuses} => {
+                let mut record: Vec<u8> = Vec::new();
+                put_name(&mut record, peer);
+                put(&mut record, tag::UNREAD, &(*unread as u64).to_be_bytes());
+                for (id, status) in statuses {
+                    put(&mut record, tag::STATUS, &[id.to_be_bytes().as_slice(), &[status.code()]].concat());
+                }
+                put(&mut buf, tag::HISTORY, &record);
+            },
+            Self::Removed(Name::Address(address)) => put(&mut buf, tag::REMOVED_ADDRESS, address.as_bytes()),
+            Self::Removed(Name::Host(host)) => put(&mut buf, tag::REMOVED, host.as_bytes())
+        }
+        buf
+    }
+    /// Read a record, `None` if it is not a single entry with a known tag that makes sense
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut entries = Entries(bytes);
+        let (record_tag, value) = entries.next()?;
+        if !entries.0.is_empty() {
+            return None
+        }
+
+        match record_tag {
+            tag::PEER => format::peer_from_bytes(value).map(Self::Peer),
+            tag::REMOVED => text(value).map(|host| Self::Removed(Name::Host(host))),
+            tag::REMOVED_ADDRESS => text(value).map(|address| Self::Removed(Name::Address(address))),
+            tag::MESSAGE | tag::HISTORY => {
+                let mut peer: Option<Name> = None;
+                let mut unread: usize = 0;
+                let mut msg: Option<msg::Message> = None;
+                let mut statuses: Vec<(u64, msg::MessageStatus)> = Vec::new();
+
+                let mut entries = Entries(value);
+                for (tag, value) in entries.by_ref() {
+                    match tag {
+                        tag::HOST => peer = Some(Name::Host(text(value)?)),
+                        tag::ADDRESS => peer = Some(Name::Address(text(value)?)),
+                        tag::UNREAD => unread = usize::try_from(number(value)?).ok()?,
+                        tag::RECORD => msg = Some(format::message_from_bytes(value)?),
+                        tag::STATUS => match value.split_last() {
+                            Some((status, id)) => statuses.push((number(id)?, msg::MessageStatus::from(*status))),
+                            None => return None
+                        },
+                        _ => ()
+                    }
+                }
+                if !entries.0.is_empty() {
+                    return None
+                }
+
+                match record_tag {
+                    tag::MESSAGE => Some(Self::Message {peer: peer?, unread, msg: msg?}),
+                    _ => Some(Self::History {peer: peer?, unread, statuses})
+                }
+            },
+            _ => None
+        }
+    }
+
+    /// Apply the record to `histories`
+    pub fn apply(self, histories: &mut Vec<msg::ChatHistory>) {
+        match self {
+            Self::Peer(history) => {
+                let peer = history.peer();
+                let same = |known: &msg::ChatHistory| {
+                    known.peer().address() == peer.address() || peer.identity().is_some_and(|identity| known.peer().identity() == Some(identity.clone()))
+                };
+                // every other match comes after the first, so it keeps its place
+                let at = histories.iter().position(same).unwrap_or(histories.len());
+                histories.retain(|known| !same(known));
+                histories.insert(at, history);
+            },
+            Self::Message {peer, unread, msg} => {
+                let Some(history) = histories.iter_mut().find(|history| peer.is(&history.peer())) else {return};
+                if history.find_msg(msg.id()).is_none() {
+                    history.push_msg(msg);
+                }
+                history.set_unread(unread);
+            },
+            Self::History {peer, unread, statuses} => {
+                let Some(history) = histories.iter_mut().find(|history| peer.is(&history.peer())) else {return};
+                history.set_statuses(&statuses);
+                history.set_unread(unread);
+            },
+            Self::Removed(peer) => histories.retain(|history| !peer.is(&history.peer()))
+        }
+    }
+}
+
+fn put_name(buf: &mut Vec<u8>, name: &Name) {
+    match name {
+        Name::Address(address) => put(buf, tag::ADDRESS, address.as_bytes()),
+        Name::Host(host) => put(buf, tag::HOST, host.as_bytes())
+    }
+}
+
+/// The bytes every journal starts with
+pub fn header() -> Vec<u8> {
+    [MAGIC.as_slice(), &VERSION.to_be_bytes()].concat()
+}
+
+/// The frame `record` is appended to the journal in, sealed with `key` if there is one
+pub fn frame(record: &Record, key: Option<&lock::Key>) -> Vec<u8> {
+    let mut bytes = record.to_bytes();
+    if let Some(key) = key {
+        bytes = lock::seal_record(&bytes, key).unwrap();
+    }
+    let checksum = Sha256::digest(&bytes);
+
+    let mut buf: Vec<u8> = Vec::new();
+    buf.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    buf.extend_from_slice(&checksum[..CHECKSUM_SIZE]);
+    buf.push(key.is_some() as u8);
+    buf.extend_from_slice(&bytes);
+    buf
+}
+
+/// What could be read from a journal
+pub struct Read {
+    /// Every record that was intact, in order
+    pub records: Vec<Record>,
+    /// How many bytes at the start of the journal were intact, the rest can be cut off
+    pub intact: usize,
+    /// Whether a frame was torn
+    pub torn: bool,
+    /// How many intact records could not be read
+    pub skipped: usize,
+    /// Whether the journal was written by a newer version, none of it is read then
+    pub newer: bool
+}
+
+/// Read every record of a journal. Sealed records are opened with `key`, reading stops at the first
+/// one it does not open, which is not counted as torn. So is a journal written by a newer version.
+pub fn read(bytes: &[u8], key: Option<&lock::Key>) -> Read {
+    let mut read = Read {records: Vec::new(), intact: 0, torn: false, skipped: 0, newer: false};
+    let Some(version) = bytes.strip_prefix(MAGIC).and_then(|rest| rest.get(..2)) else {
+        read.torn = !bytes.is_empty();
+        return read
+    };
+    if u16::from_be_bytes([version[0], version[1]]) > VERSION {
+        eprintln!("JOURNAL WAS WRITTEN BY A NEWER VERSION");
+        read.intact = bytes.len();
+        read.newer = true;
+        return read
+    }
+
+    read.intact = header().len();
+    while read.intact < bytes.len() {
+        let rest = &bytes[read.intact..];
+        let Some(frame_header) = rest.get(..FRAME_HEADER_SIZE) else {
+            read.torn = true;
+            break
+        };
+        let length = u32::from_be_bytes(frame_header[..4].try_into().unwrap()) as usize;
+        let Some(stored) = rest.get(FRAME_HEADER_SIZE..FRAME_HEADER_SIZE + length) else {
+            read.torn = true;
+            break
+        };
+        if Sha256::digest(stored)[..CHECKSUM_SIZE] != frame_header[4..4 + CHECKSUM_SIZE] {
+            read.torn = true;
+            break
+        }
+
+        let opened = match (frame_header[FRAME_HEADER_SIZE - 1], key) {
+            (0, _) => Some(stored.to_vec()),
+            (_, Some(key)) => lock::open_record(stored, key),
+            (_, None) => None
+        };
+        let Some(opened) = opened else { break };
+        match Record::from_bytes(&opened) {
+            Some(record) => read.records.push(record),
+            None => {
+                eprintln!("SKIPPED A JOURNAL RECORD THAT COULD NOT BE READ");
+                read.skipped += 1;
+            }
+        }
+        read.intact += FRAME_HEADER_SIZE + length;
+    }
+    read
+}
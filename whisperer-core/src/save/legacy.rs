@@ -0,0 +1,143 @@
+//! Reading the `RECIPIENTS` and `HISTORY` files that were saved before the data had a versioned format
+//!
+//! `RECIPIENTS` has one line per peer: its IP and alias ending in NUL bytes, and the raw bytes of the
+//! key it was sent with, if there was one. `HISTORY` has the host of every peer on a line, followed
+//! by a line per message and an empty line, so messages containing a blank line end the history
+//! early. Both are only read to upgrade them to `format`.
+use crate::{msg, tcp::vector as vect};
+
+/// Read the contents of the `RECIPIENTS` and `HISTORY` files, along with how many histories were
+/// dropped because their peer is not in `RECIPIENTS`. `None` if `RECIPIENTS` is damaged.
+pub fn from_bytes(recipient_data: &[u8], history_data: &[u8]) -> Option<(Vec<msg::ChatHistory>, usize)> {
+    let mut histories: Vec<msg::ChatHistory> = Vec::new();
+    let mut orphans: usize = 0;
+
+    {
+        let mut rest = recipient_data;
+        while !rest.is_empty() {
+            let (rec, after) = read_recipient(rest)?;
+            histories.push(msg::ChatHistory::new(rec));
+            rest = after;
+        }
+
+        {
+            let mut ip: Vec<u8> = Vec::new();
+            let mut message_read: Vec<u8> = Vec::new();
+            let mut messages: Vec<msg::Message> = Vec::new();
+            let mut unread: usize = 0;
+            let mut newline_count: u8 = 0;
+            let mut mode: u8 = 0;
+            let mut you = false;
+            
+            for byte in history_data.iter() {
+                match mode {
+                    0 => {
+                        if byte == &10 {
+                            // the host is followed by the unread count after a `\x01`, if there are unread messages
+                            if let Some(end) = ip.iter().position(|b| *b == 1) {
+                                unread = vect::bytes_to_string(&ip[end + 1..]).parse().unwrap_or(0);
+                                ip.truncate(end);
+                            }
+                            mode = 1
+                        } else {
+                            ip.push(*byte)
+                        }
+                    },
+                    1 => {
+                        if byte == &10 {
+                            newline_count += 1;
+                            if newline_count == 2 {
+                                let host = vect::bytes_to_string(ip.clone());
+                                if let Some(chathistory) = histories.iter_mut().find(|history| history.peer().ip() == host) {
+                                    for message in messages.iter() {
+                                        chathistory.push_msg(message.clone());
+                                    }
+                                    chathistory.set_unread(unread);
+                                } else {
+                                    orphans += 1;
+                                }
+
+                                ip.clear();
+                                message_read.clear();
+                                messages.clear();
+                                unread = 0;
+                                newline_count = 0;
+                                mode = 0;
+                            } else {
+                                let author = match you {
+                                    true => String::from("You"),
+                                    false => vect::bytes_to_string(ip.clone()),
+                                };
+                                messages.push(read_message(author, &message_read));
+                                message_read.clear();
+                            }
+                            you = false;
+                        } else {
+                            newline_count = 0;
+                            if byte == &0 {
+                                you = true;
+                            } else {
+                                message_read.push(*byte);
+                            }
+                        }
+                    },
+                    _ => unreachable!()
+                }
+            }
+        }
+    }
+
+    Some((histories, orphans))
+}
+
+/// Read one message line of `HISTORY`. The line starts with the message ID, its timestamps and its
+/// status between `\x01` and `\x02`, lines saved before messages had them get a new ID instead.
+fn read_message(author: String, line: &[u8]) -> msg::Message {
+    let details = line.strip_prefix(&[1u8])
+        .and_then(|rest| rest.iter().position(|b| *b == 2).map(|end| rest.split_at(end)));
+    let Some((details, content)) = details else {
+        return msg::Message::with_details(rand::random(), author, vect::bytes_to_string(line), 0, 0);
+    };
+
+    let details = vect::bytes_to_string(details);
+    let numbers: Vec<u64> = details.split(' ').filter_map(|n| n.parse().ok()).collect();
+    let content = vect::bytes_to_string(&content[1..]);
+    match numbers.as_slice() {
+        [id, sent_at, received_at] => msg::Message::with_details(*id, author, content, *sent_at, *received_at),
+        [id, sent_at, received_at, status] => {
+            let mut message = msg::Message::with_details(*id, author, content, *sent_at, *received_at);
+            message.set_status(msg::MessageStatus::from(u8::try_from(*status).unwrap_or(u8::MAX)));
+            message
+        },
+        _ => msg::Message::with_details(rand::random(), author, content, 0, 0)
+    }
+}
+
+/// The size of the keys versions before X25519 saved
+const OLD_KEY_SIZE: usize = 16;
+
+/// Read the `RECIPIENTS` entry at the start of `data`, along with what follows it. The IP and alias
+/// end in NUL bytes and the rest of the line is the old key. A key can contain newline bytes, so one
+/// of its size is read by length, unless the line ends right away like the old versions read it. The
+/// key cannot encrypt anything for X25519 and is dropped, the peer gets a new one. `None` if the
+/// entry does not end in a newline.
+fn read_recipient(data: &[u8]) -> Option<(msg::Recipient, &[u8])> {
+    let (host, rest) = split_at_nul(data)?;
+    let (alias, rest) = split_at_nul(rest)?;
+
+    let mut rec = msg::Recipient::from(vect::bytes_to_string(host));
+    if !alias.is_empty() { rec.set_alias(Some(vect::bytes_to_string(alias))) }
+
+    let rest = match (rest.first(), rest.get(OLD_KEY_SIZE)) {
+        (Some(10), _) => &rest[1..],
+        (_, Some(10)) => &rest[OLD_KEY_SIZE + 1..],
+        _ => &rest[rest.iter().position(|b| *b == 10)? + 1..]
+    };
+    Some((rec, rest))
+}
+
+/// The bytes before the first NUL byte of `data`, and the bytes after it
+fn split_at_nul(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let end = data.iter().position(|b| *b == 0)?;
+    Some((&data[..end], &data[end + 1..]))
+}
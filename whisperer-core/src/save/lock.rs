@@ -0,0 +1,91 @@
+//! Sealing the `PEERS` and `IDENTITY` files with a key derived from a passphrase
+//!
+//! A sealed file starts with `MAGIC` and the version of the envelope as a big-endian u16, followed by
+//! the Argon2id costs the key was derived with (memory in KiB and passes, both big-endian u32), the
+//! salt, and the contents in the `format` of the file, encrypted with `kem::encrypt`.
+//! The costs and salt are stored so the key can be derived again, they are not secret.
+use crate::{kem::{self, SALT_SIZE}, tcp::vector as vect};
+
+/// The first bytes of every sealed file
+pub const MAGIC: &[u8; 4] = b"WHSE";
+/// The version of the envelope written by this build
+pub const VERSION: u16 = 1;
+/// The memory in KiB a key is derived with, large enough to make every guess costly
+pub const MEMORY: u32 = 64 * 1024;
+/// The passes over the memory a key is derived with
+pub const PASSES: u32 = 3;
+/// The size of the header in front of the encrypted contents
+const HEADER_SIZE: usize = MAGIC.len() + 2 + 4 + 4 + SALT_SIZE;
+
+/// A key derived from a passphrase, along with what it was derived with
+#[derive(Clone)]
+pub struct Key {
+    key: Vec<u8>,
+    salt: Vec<u8>,
+    memory: u32,
+    passes: u32
+}
+impl Key {
+    /// Derive a key from `passphrase` with a fresh salt and the given costs
+    pub fn new(passphrase: &str, memory: u32, passes: u32) -> Result<Self, kem::Error> {
+        let salt = vect::rand_byte_vector(SALT_SIZE);
+        let key = kem::passphrase_key(passphrase, &salt, memory, passes)?;
+        Ok(Self {key, salt, memory, passes})
+    }
+    /// Derive the key `sealed` was sealed with from `passphrase`. Whether it is the right passphrase
+    /// only shows when opening it.
+    pub fn for_sealed(passphrase: &str, sealed: &[u8]) -> Option<Self> {
+        let header = header(sealed)?;
+        let memory = u32::from_be_bytes(header[6..10].try_into().ok()?);
+        let passes = u32::from_be_bytes(header[10..14].try_into().ok()?);
+        let salt = header[14..].to_vec();
+        let key = kem::passphrase_key(passphrase, &salt, memory, passes).ok()?;
+        Some(Self {key, salt, memory, passes})
+    }
+    /// Whether `passphrase` derives this key
+    pub fn matches(&self, passphrase: &str) -> bool {
+        kem::passphrase_key(passphrase, &self.salt, self.memory, self.passes).is_ok_and(|key| key == self.key)
+    }
+}
+
+/// Whether `bytes` are sealed, in any version of the envelope
+pub fn is_sealed(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Encrypt `contents` with `key` and put them in the envelope
+pub fn seal(contents: &[u8], key: &Key) -> Result<Vec<u8>, kem::Error> {
+    let mut buf: Vec<u8> = MAGIC.to_vec();
+    buf.extend_from_slice(&VERSION.to_be_bytes());
+    buf.extend_from_slice(&key.memory.to_be_bytes());
+    buf.extend_from_slice(&key.passes.to_be_bytes());
+    buf.extend_from_slice(&key.salt);
+    buf.extend_from_slice(&kem::encrypt(contents, key.key.clone())?);
+    Ok(buf)
+}
+
+/// Take the contents out of the envelope, `None` if `key` does not open it or it was changed
+pub fn open(sealed: &[u8], key: &Key) -> Option<Vec<u8>> {
+    header(sealed)?;
+    if sealed[HEADER_SIZE - SALT_SIZE..HEADER_SIZE] != key.salt[..] {
+        return None
+    }
+    kem::decrypt(&sealed[HEADER_SIZE..], key.key.clone()).ok()
+}
+
+/// Encrypt a single record with `key`, for files that are written a record at a time
+pub fn seal_record(record: &[u8], key: &Key) -> Result<Vec<u8>, kem::Error> {
+    kem::encrypt(record, key.key.clone())
+}
+
+/// Decrypt a record made with `seal_record`, `None` if `key` does not open it or it was changed
+pub fn open_record(sealed: &[u8], key: &Key) -> Option<Vec<u8>> {
+    kem::decrypt(sealed, key.key.clone()).ok()
+}
+
+/// The header of `sealed`, `None` if it is cut short or was written by a newer version
+fn header(sealed: &[u8]) -> Option<&[u8]> {
+    let header = sealed.get(..HEADER_SIZE).filter(|header| is_sealed(header))?;
+    let version = u16::from_be_bytes(header[4..6].try_into().ok()?);
+    (version <= VERSION).then_some(header)
+}
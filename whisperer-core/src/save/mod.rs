@@ -0,0 +1,598 @@
+#![allow(unused)] // because rust-analyzer is smarter than gpt-4.5 and can clearly understand that im using everything
+use crate::{msg, kem::{self, KEY_SIZE, IDENTITY_SIZE}, tcp::{self, vector as vect, file}, peers::PeerStore};
+use std::{path::{Path, PathBuf}, fs, env::var, io::{self, Read, Write}, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex}};
+
+pub mod format;
+pub mod lock;
+pub mod journal;
+mod legacy;
+mod error;
+
+pub use error::{Error, Problem};
+
+/// The key the `PEERS` file is sealed with, once its passphrase was given or set.
+/// `None` while the data has no passphrase, or its passphrase was not given yet.
+static KEY: Mutex<Option<lock::Key>> = Mutex::new(None);
+/// Held while the journal is written to, read or started over
+static JOURNAL: Mutex<()> = Mutex::new(());
+/// Set once `get_data` found a `PEERS` file written by a newer version, which is never saved over
+static NEWER: AtomicBool = AtomicBool::new(false);
+/// The size in bytes the journal may grow to before everything is saved to `PEERS` and it starts over
+pub const JOURNAL_LIMIT: u64 = 1024 * 1024;
+
+/// Store every peer of `peers` and its chat history in the `PEERS` file, see `format`, and start a new journal.
+/// It is sealed if a passphrase is set, and left alone while the passphrase was not given.
+pub fn set_data(peers: &PeerStore) {
+    write_data(|| peers.histories())
+}
+
+/// Store the histories `snapshot` returns in the `PEERS` file and start a new journal. The snapshot is
+/// taken while `JOURNAL` is held, so a change journaled by another thread is either in it or in the new journal.
+fn write_data(snapshot: impl FnOnce() -> Vec<msg::ChatHistory>) {
+    let path = root_path();
+    if let Err(e) = fs::create_dir_all(&path) {
+        eprintln!("COULD NOT SAVE: {e}");
+        return
+    }
+
+    // the key is read while `JOURNAL` is held, so `set_passphrase` cannot change it in between
+    let _journal = JOURNAL.lock().unwrap();
+    let key = KEY.lock().unwrap().clone();
+    if key.is_none() && locked() {
+        eprintln!("NOT SAVING, DATA IS LOCKED");
+        return
+    }
+    if NEWER.load(Ordering::Relaxed) {
+        eprintln!("NOT SAVING, DATA IS FROM A NEWER VERSION");
+        return
+    }
+    let bytes = format::to_bytes(&snapshot());
+    let written = match key {
+        Some(key) => lock::seal(&bytes, &key).map_err(io::Error::other),
+        None => Ok(bytes)
+    }.and_then(|bytes| write_atomic(&peers_path(), &bytes));
+    match written {
+        // the journal is only started over once everything in it reached `PEERS`
+        Ok(()) => fs::remove_file(journal_path()).unwrap_or(()),
+        Err(e) => eprintln!("COULD NOT SAVE: {e}")
+    }
+}
+
+/// Save every change to `peers` the moment it is made, by appending it to the journal.
+/// Once the journal grows past `JOURNAL_LIMIT`, everything is saved with `set_data`.
+pub fn keep_saved(peers: &Arc<PeerStore>) {
+    let store = Arc::clone(peers);
+    peers.watch(move |change| {
+        let Some(record) = journal::Record::for_change(&store, &change) else {return};
+        match append(&record) {
+            Ok(size) if size > JOURNAL_LIMIT => set_data(&store),
+            Ok(_) => (),
+            Err(e) => {
+                eprintln!("COULD NOT WRITE THE JOURNAL: {e}");
+                set_data(&store);
+            }
+        }
+    });
+}
+
+/// Append `record` to the journal and wait for it to reach the disk, returns the size of the journal
+fn append(record: &journal::Record) -> io::Result<u64> {
+    let _journal = JOURNAL.lock().unwrap();
+    let key = KEY.lock().unwrap().clone();
+    if key.is_none() && locked() {
+        eprintln!("NOT SAVING, DATA IS LOCKED");
+        return Ok(0)
+    }
+    if NEWER.load(Ordering::Relaxed) {
+        eprintln!("NOT SAVING, DATA IS FROM A NEWER VERSION");
+        return Ok(0)
+    }
+    let root = root_path();
+    if !root.exists() {
+        fs::create_dir_all(&root)?;
+    }
+
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(journal_path())?;
+    let mut bytes: Vec<u8> = Vec::new();
+    if file.metadata()?.len() == 0 {
+        bytes = journal::header();
+    }
+    bytes.extend_from_slice(&journal::frame(record, key.as_ref()));
+    file.write_all(&bytes)?;
+    file.sync_data()?;
+    Ok(file.metadata()?.len())
+}
+
+/// Apply the journal to `histories`, and cut off a frame that was torn so later records can follow
+/// the intact ones. A journal with records that could not be read is copied before that, and one
+/// written by a newer version is moved out of the way so a save does not remove it. The caller holds `JOURNAL`.
+fn replay_journal(histories: &mut Vec<msg::ChatHistory>, key: Option<&lock::Key>) -> Option<Problem> {
+    let file = journal_path();
+    let Ok(bytes) = fs::read(&file) else {return None};
+    let read = journal::read(&bytes, key);
+    if !read.records.is_empty() {
+        eprintln!("REPLAY {} JOURNAL RECORDS", read.records.len());
+    }
+    for record in read.records {
+        record.apply(histories);
+    }
+
+    if read.newer {
+        let backup = quarantine(&file, false);
+        return Some(Problem::Unreadable {file, backup})
+    }
+    if !read.torn && read.skipped == 0 {
+        return None
+    }
+    let backup = quarantine(&file, true);
+    if read.torn {
+        eprintln!("DROP TORN JOURNAL RECORD");
+        fs::OpenOptions::new().write(true).open(&file)
+            .and_then(|file| file.set_len(read.intact as u64))
+            .unwrap_or(());
+    }
+    Some(Problem::Damaged {file, skipped: read.skipped + read.torn as usize, backup})
+}
+
+/// Keep the damaged `file` next to it, named after when it was found, so nothing that could not be
+/// read is overwritten. It is copied if `keep` is set and moved otherwise. Returns where it was kept.
+fn quarantine(file: &Path, keep: bool) -> Option<PathBuf> {
+    let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    let mut backup = PathBuf::from(format!("{}.corrupt-{stamp}", file.to_string_lossy()));
+    let mut copy = 1;
+    while backup.exists() {
+        backup = PathBuf::from(format!("{}.corrupt-{stamp}-{copy}", file.to_string_lossy()));
+        copy += 1;
+    }
+    let kept = match keep {
+        true => fs::copy(file, &backup).map(|_| ()),
+        false => fs::rename(file, &backup)
+    };
+    match kept {
+        Ok(()) => {
+            eprintln!("KEPT {} AS {}", file.to_string_lossy(), backup.to_string_lossy());
+            Some(backup)
+        },
+        Err(e) => {
+            eprintln!("COULD NOT KEEP {}: {e}", file.to_string_lossy());
+            None
+        }
+    }
+}
+
+/// Replace the file at `path` with `bytes`, so that it holds either all of the old or all of the new
+/// contents, even if the process or the computer stops while it is written
+fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let partial = path.with_extension("partial");
+    let mut file = fs::File::create(&partial)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(&partial, path)?;
+    // the rename itself only lasts once the directory reaches the disk
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        fs::File::open(dir).and_then(|dir| dir.sync_all()).unwrap_or(());
+    }
+    Ok(())
+}
+
+/// Load every peer with its chat history, along with whatever went wrong while loading it.
+/// Sealed data loads no peers until `unlock` was called.
+/// Without a `PEERS` file, the `RECIPIENTS` and `HISTORY` files of older versions are read once, stored
+/// as `PEERS`, and kept with a `.legacy` extension. If `RECIPIENTS` is damaged, both are left as they
+/// are and nothing is loaded from them. Whatever changed after `PEERS` was last written is
+/// applied from the journal.
+/// Records that cannot be read are skipped and the rest is loaded, after the damaged file was kept with
+/// `quarantine`. What was loaded is then saved in its place. A `PEERS` file written by a newer version
+/// loads nothing and is left as it is, and nothing is saved for as long as the app runs.
+/// A message that was still pending when the app closed never got an answer, so it is loaded as failed.
+pub fn get_data() -> (Vec<msg::ChatHistory>, Vec<Problem>) {
+    let root = root_path();
+    let mut problems: Vec<Problem> = Vec::new();
+    if platform_root().is_none() {
+        problems.push(Problem::NoDataDirectory(root.clone()));
+    }
+
+    let s = std::path::MAIN_SEPARATOR;
+    let peers_file = peers_path();
+    let recipient_file = PathBuf::from(format!("{}{s}RECIPIENTS", root.to_string_lossy()));
+    let history_file = PathBuf::from(format!("{}{s}HISTORY", root.to_string_lossy()));
+
+    let key = KEY.lock().unwrap().clone();
+    let mut histories = if let Ok(data) = fs::read(&peers_file) {
+        let opened = match (lock::is_sealed(&data), &key) {
+            (false, _) => Some(data),
+            (true, Some(key)) => lock::open(&data, key),
+            (true, None) => {
+                eprintln!("DATA IS LOCKED");
+                problems.push(Problem::Locked);
+                return (Vec::new(), problems)
+            }
+        };
+        match opened.as_deref().and_then(format::recover) {
+            Some((histories, 0)) => histories,
+            Some((histories, skipped)) => {
+                eprintln!("SKIPPED {skipped} DAMAGED RECORDS IN {}", peers_file.to_string_lossy());
+                let backup = quarantine(&peers_file, true);
+                problems.push(Problem::Damaged {file: peers_file.clone(), skipped, backup});
+                histories
+            },
+            None if opened.as_deref().is_some_and(format::newer) => {
+                eprintln!("{} WAS WRITTEN BY A NEWER VERSION", peers_file.to_string_lossy());
+                NEWER.store(true, Ordering::Relaxed);
+                problems.push(Problem::Newer(peers_file));
+                return (Vec::new(), problems)
+            },
+            None => {
+                eprintln!("COULD NOT READ {}", peers_file.to_string_lossy());
+                let backup = quarantine(&peers_file, false);
+                problems.push(Problem::Unreadable {file: peers_file.clone(), backup});
+                Vec::new()
+            }
+        }
+    } else if let (Ok(recipient_data), Ok(history_data)) = (fs::read(&recipient_file), fs::read(&history_file)) {
+        eprintln!("UPGRADE RECIPIENTS AND HISTORY");
+        // the legacy files are left as they are until they can be read
+        let Some((histories, orphans)) = legacy::from_bytes(&recipient_data, &history_data) else {
+            eprintln!("COULD NOT READ {}", recipient_file.to_string_lossy());
+            problems.push(Problem::Unreadable {file: recipient_file.clone(), backup: Some(recipient_file)});
+            return (Vec::new(), problems)
+        };
+        write_data(|| histories.clone());
+        fs::rename(&recipient_file, format!("{}.legacy", recipient_file.to_string_lossy())).unwrap_or(());
+        let backup = PathBuf::from(format!("{}.legacy", history_file.to_string_lossy()));
+        let backup = fs::rename(&history_file, &backup).ok().map(|_| backup);
+        if orphans > 0 {
+            eprintln!("DROPPED {orphans} HISTORIES OF UNKNOWN PEERS");
+            problems.push(Problem::Damaged {file: history_file, skipped: orphans, backup});
+        }
+        histories
+    } else {
+        Vec::new()
+    };
+
+    let journal = JOURNAL.lock().unwrap();
+    problems.extend(replay_journal(&mut histories, key.as_ref()));
+    drop(journal);
+
+    for history in histories.iter_mut() {
+        for msg in history.history().iter().filter(|msg| msg.status() == msg::MessageStatus::Pending) {
+            history.set_status(msg.id(), msg::MessageStatus::Failed);
+        }
+    }
+    // a damaged file is only replaced once it was kept
+    let kept: Vec<bool> = problems.iter().filter_map(|problem| match problem {
+        Problem::Unreadable {backup, ..} | Problem::Damaged {backup, ..} => Some(backup.is_some()),
+        _ => None
+    }).collect();
+    if !kept.is_empty() && kept.iter().all(|kept| *kept) {
+        write_data(|| histories.clone());
+    }
+    (histories, problems)
+}
+
+/// Whether the data is sealed with a passphrase that was not given yet.
+/// Until `unlock` is called with it, nothing is loaded and nothing is saved.
+pub fn locked() -> bool {
+    KEY.lock().unwrap().is_none() && sealed()
+}
+
+/// Whether the data is kept sealed with a passphrase
+pub fn encrypted() -> bool {
+    KEY.lock().unwrap().is_some() || sealed()
+}
+
+/// Give the passphrase the data is sealed with, so it can be loaded and saved
+pub fn unlock(passphrase: &str) -> Result<(), Error> {
+    let sealed = fs::read(peers_path())?;
+    if !lock::is_sealed(&sealed) {
+        return Ok(())
+    }
+    let key = lock::Key::for_sealed(passphrase, &sealed).ok_or(Error::Unreadable)?;
+    lock::open(&sealed, &key).ok_or(Error::WrongPassphrase)?;
+    *KEY.lock().unwrap() = Some(key);
+    Ok(())
+}
+
+/// Seal the data and the identity with the passphrase `new` from now on, or keep them in the clear if it is `None`.
+/// If the data has a passphrase already, `current` has to be that passphrase.
+/// Setting one removes the copies kept from upgrading the legacy files, which are not sealed.
+pub fn set_passphrase(current: Option<&str>, new: Option<&str>) -> Result<(), Error> {
+    // nothing is saved while the files are read and sealed again, `JOURNAL` is always taken before `KEY`
+    let _journal = JOURNAL.lock().unwrap();
+    let mut held = KEY.lock().unwrap();
+    let stored = match fs::read(peers_path()) {
+        Ok(stored) => Some(stored),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into())
+    };
+
+    let old_key = match (held.clone(), &stored) {
+        (Some(key), _) => Some(key),
+        (None, Some(stored)) if lock::is_sealed(stored) => {
+            let current = current.ok_or(Error::WrongPassphrase)?;
+            Some(lock::Key::for_sealed(current, stored).ok_or(Error::Unreadable)?)
+        },
+        (None, _) => None
+    };
+    if let Some(key) = &old_key {
+        if !current.is_some_and(|current| key.matches(current)) {
+            return Err(Error::WrongPassphrase)
+        }
+    }
+
+    // without a `PEERS` file yet, an empty one keeps the passphrase
+    let contents = match (stored, &old_key) {
+        (Some(stored), Some(key)) if lock::is_sealed(&stored) => lock::open(&stored, key).ok_or(Error::WrongPassphrase)?,
+        (Some(stored), _) => stored,
+        (None, _) => format::to_bytes(&[])
+    };
+    // the identity is sealed with the same key, it is opened before anything is written
+    let identity = match fs::read(identity_path()) {
+        Ok(stored) if lock::is_sealed(&stored) => {
+            let key = old_key.as_ref().ok_or(Error::WrongPassphrase)?;
+            Some(lock::open(&stored, key).ok_or(Error::Unreadable)?)
+        },
+        Ok(stored) => Some(stored),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into())
+    };
+    // the journal is sealed with the old key, so it is folded into the new `PEERS`
+    let mut histories = format::from_bytes(&contents).ok_or(Error::Unreadable)?;
+    replay_journal(&mut histories, old_key.as_ref());
+    let contents = format::to_bytes(&histories);
+    let new_key = new.map(|new| lock::Key::new(new, lock::MEMORY, lock::PASSES)).transpose().map_err(io::Error::other)?;
+    let bytes = match &new_key {
+        Some(key) => lock::seal(&contents, key).map_err(io::Error::other)?,
+        None => contents
+    };
+    let root = root_path();
+    if !root.exists() {
+        fs::create_dir_all(&root)?;
+    }
+    write_atomic(&peers_path(), &bytes)?;
+    fs::remove_file(journal_path()).unwrap_or(());
+    if let Some(identity) = identity {
+        write_identity(&identity, new_key.as_ref());
+    }
+    if new_key.is_some() {
+        let s = std::path::MAIN_SEPARATOR;
+        fs::remove_file(format!("{}{s}RECIPIENTS.legacy", root.to_string_lossy())).unwrap_or(());
+        fs::remove_file(format!("{}{s}HISTORY.legacy", root.to_string_lossy())).unwrap_or(());
+    }
+    *held = new_key;
+    Ok(())
+}
+
+/// Whether the `PEERS` file is sealed, only its first bytes are read
+fn sealed() -> bool {
+    let mut magic = [0u8; lock::MAGIC.len()];
+    fs::File::open(peers_path()).and_then(|mut file| file.read_exact(&mut magic)).is_ok() && lock::is_sealed(&magic)
+}
+
+fn peers_path() -> PathBuf {
+    let s = std::path::MAIN_SEPARATOR;
+    PathBuf::from(format!("{}{s}PEERS", root_path().to_string_lossy()))
+}
+
+fn journal_path() -> PathBuf {
+    let s = std::path::MAIN_SEPARATOR;
+    PathBuf::from(format!("{}{s}JOURNAL", root_path().to_string_lossy()))
+}
+
+fn identity_path() -> PathBuf {
+    let s = std::path::MAIN_SEPARATOR;
+    PathBuf::from(format!("{}{s}IDENTITY", root_path().to_string_lossy()))
+}
+
+/// Settings that are kept between runs
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    /// The port to listen for other computers on
+    pub listen_port: u16,
+    /// Whether to announce this computer and look for others on the local network
+    pub discovery: bool
+}
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            listen_port: tcp::DEFAULT_PORT,
+            discovery: false
+        }
+    }
+}
+
+/// Load the settings, missing or unreadable values keep their default.
+/// The `SETTINGS` file has one `name=value` pair per line.
+pub fn get_settings() -> Settings {
+    let path = root_path();
+    let s = std::path::MAIN_SEPARATOR;
+    let mut settings = Settings::default();
+
+    let Ok(file) = fs::read_to_string(format!("{}{s}SETTINGS", path.to_string_lossy())) else { return settings };
+    for line in file.lines() {
+        let Some((name, value)) = line.split_once('=') else { continue };
+        match name.trim() {
+            "listen_port" => if let Ok(port @ 1..) = value.trim().parse::<u16>() { settings.listen_port = port },
+            "discovery" => if let Ok(discovery) = value.trim().parse() { settings.discovery = discovery },
+            _ => ()
+        }
+    }
+    settings
+}
+
+pub fn set_settings(settings: &Settings) -> io::Result<()> {
+    let path = root_path();
+    if !path.exists() {
+        fs::create_dir_all(&path)?;
+    }
+    let s = std::path::MAIN_SEPARATOR;
+
+    fs::write(
+        format!("{}{s}SETTINGS", path.to_string_lossy()),
+        format!("listen_port={}\ndiscovery={}\n", settings.listen_port, settings.discovery)
+    )
+}
+
+/// Store where the running daemon takes requests: its port on the loopback address and the token
+/// every request has to start with. Only the owner of the data directory may read the `DAEMON` file.
+pub fn set_daemon(port: u16, token: &[u8]) -> io::Result<()> {
+    let path = root_path();
+    if !path.exists() {
+        fs::create_dir_all(&path)?;
+    }
+    let s = std::path::MAIN_SEPARATOR;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let token: String = token.iter().map(|b| format!("{b:02x}")).collect();
+    let mut file = options.open(format!("{}{s}DAEMON", path.to_string_lossy()))?;
+    std::io::Write::write_all(&mut file, format!("{port}\n{token}\n").as_bytes())
+}
+
+/// The port and token of the daemon, `None` if none was started. The daemon may have stopped since.
+pub fn get_daemon() -> Option<(u16, Vec<u8>)> {
+    let path = root_path();
+    let s = std::path::MAIN_SEPARATOR;
+
+    let file = fs::read_to_string(format!("{}{s}DAEMON", path.to_string_lossy())).ok()?;
+    let mut lines = file.lines();
+    let port = lines.next()?.trim().parse().ok()?;
+    let token = lines.next()?.trim();
+    let token = (0..token.len()).step_by(2)
+        .map(|i| token.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect::<Option<Vec<u8>>>()?;
+    Some((port, token))
+}
+
+/// Forget the daemon once it stops
+pub fn clear_daemon() {
+    let path = root_path();
+    let s = std::path::MAIN_SEPARATOR;
+    fs::remove_file(format!("{}{s}DAEMON", path.to_string_lossy())).unwrap_or(());
+}
+
+/// Load this install's identity, generating and storing a new one if there is none yet.
+/// While a passphrase is set the identity is sealed with the same key as `PEERS`, so it can only be
+/// loaded after `unlock`. One that was stored in the clear before is sealed the first time it is loaded.
+/// An identity that cannot be read is moved out of the way with `quarantine` before it is replaced.
+/// A sealed one the key does not open is left alone instead, and a temporary identity is used until
+/// the app restarts.
+pub fn get_identity() -> kem::Identity {
+    let identity_file = identity_path();
+    let key = KEY.lock().unwrap().clone();
+
+    if let Ok(bytes) = fs::read(&identity_file) {
+        let sealed = lock::is_sealed(&bytes);
+        let opened = match (sealed, &key) {
+            (false, _) => Some(bytes),
+            (true, Some(key)) => lock::open(&bytes, key),
+            (true, None) => None
+        };
+        match opened.as_deref().and_then(kem::identity_from_bytes) {
+            Some(identity) => {
+                if !sealed && key.is_some() {
+                    write_identity(&identity.to_bytes(), key.as_ref());
+                }
+                return identity
+            },
+            None if sealed => {
+                eprintln!("COULD NOT OPEN {}, USING A TEMPORARY IDENTITY", identity_file.to_string_lossy());
+                return kem::generate_identity()
+            },
+            None => {
+                eprintln!("COULD NOT READ {}", identity_file.to_string_lossy());
+                quarantine(&identity_file, false);
+            }
+        }
+    }
+
+    eprintln!("GENERATE IDENTITY");
+    let identity = kem::generate_identity();
+    if key.is_none() && locked() {
+        eprintln!("NOT SAVING, DATA IS LOCKED");
+        return identity
+    }
+    write_identity(&identity.to_bytes(), key.as_ref());
+    identity
+}
+
+/// Store the identity `bytes` in the `IDENTITY` file, sealed with `key` if there is one
+fn write_identity(bytes: &[u8], key: Option<&lock::Key>) {
+    let sealed = match key {
+        Some(key) => lock::seal(bytes, key).map_err(io::Error::other),
+        None => Ok(bytes.to_vec())
+    };
+    let written = sealed.and_then(|sealed| fs::create_dir_all(root_path()).and_then(|_| write_atomic(&identity_path(), &sealed)));
+    if let Err(e) = written {
+        eprintln!("COULD NOT SAVE THE IDENTITY: {e}");
+    }
+}
+
+/// Pick a path in the download directory for a received file called `name`.
+/// Only the last component of `name` is used, and a number is added if the file already exists.
+pub fn download_path(name: &str) -> io::Result<PathBuf> {
+    unused_path(root_path().join("downloads"), name)
+}
+
+/// Pick a path in the export directory for an exported chat history called `name`, like `download_path`
+pub fn export_path(name: &str) -> io::Result<PathBuf> {
+    unused_path(root_path().join("exports"), name)
+}
+
+/// A path in `dir` for a file called `name` that does not exist yet, `dir` is created if it is missing
+fn unused_path(dir: PathBuf, name: &str) -> io::Result<PathBuf> {
+    if !dir.exists() {
+        fs::create_dir_all(&dir)?;
+    }
+
+    let name = PathBuf::from(name.replace('\\', "/"));
+    let name = match name.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => String::from("download")
+    };
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem.to_string(), format!(".{extension}")),
+        _ => (name.clone(), String::new())
+    };
+
+    let mut path = dir.join(&name);
+    let mut copy = 1;
+    while path.exists() || file::partial_path(&path).exists() {
+        path = dir.join(format!("{stem} ({copy}){extension}"));
+        copy += 1;
+    }
+    Ok(path)
+}
+
+/// The directory data is kept in, a temporary one if `platform_root` has none
+fn root_path() -> PathBuf {
+    platform_root().unwrap_or_else(|| std::env::temp_dir().join("whisperer"))
+}
+
+/// Where this platform keeps the data of apps, `None` if it is not known or its variable is not set
+fn platform_root() -> Option<PathBuf> {
+    let mut path: Option<PathBuf> = None;
+    #[cfg(target_os = "linux")]
+    {
+        path = var("HOME").ok().filter(|home| !home.is_empty())
+            .map(|home| PathBuf::from(format!("{home}/.local/share/whisperer")));
+    }
+    #[cfg(target_os = "windows")]
+    {
+        path = var("LOCALAPPDATA").ok().filter(|local| !local.is_empty())
+            .map(|local| PathBuf::from(format!("{local}/whisperer")));
+    }
+    #[cfg(target_os = "macos")]
+    {
+        path = var("HOME").ok().filter(|home| !home.is_empty())
+            .map(|home| PathBuf::from(format!("{home}/Library/Application Support/whisperer")));
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,302 @@
+#![cfg(test)]
+
+use super::*;
+
+fn sample() -> msg::ChatHistory {
+    let mut peer = msg::Recipient::from("laptop.local");
+    peer.set_port(9999);
+    peer.set_alias(Some(String::from("desk")));
+    peer.set_identity(Some(vec![10u8; IDENTITY_SIZE]));
+    peer.set_private_key(vec![10u8; KEY_SIZE]);
+    peer.set_verified(true);
+    peer.set_muted(true);
+
+    let mut history = msg::ChatHistory::new(peer);
+    let mut ours = msg::Message::with_details(1, String::from("You"), String::from("lunch?\n\nor later"), 1000, 1000);
+    ours.set_status(msg::MessageStatus::Delivered);
+    history.push_msg(ours);
+    history.push_msg(msg::Message::with_details(2, String::from("laptop.local"), String::from("now"), 2000, 2001));
+    history.set_unread(1);
+    history
+}
+
+#[test]
+fn peers_survive_the_format() {
+    let bytes = format::to_bytes(&[sample(), msg::ChatHistory::new(msg::Recipient::from("10.0.0.2"))]);
+    assert!(bytes.starts_with(format::MAGIC));
+    let histories = format::from_bytes(&bytes).unwrap();
+    assert_eq!(histories.len(), 2);
+
+    let peer = histories[0].peer();
+    assert_eq!((peer.ip(), peer.port(), peer.alias()), (String::from("laptop.local"), 9999, Some(String::from("desk"))));
+    assert_eq!(peer.identity(), Some(vec![10u8; IDENTITY_SIZE]), "newline bytes in keys are kept");
+    assert_eq!(peer.private_key(), Some(vec![10u8; KEY_SIZE]));
+    assert!(peer.verified() && peer.muted() && !peer.key_changed());
+    assert_eq!(histories[0].unread(), 1);
+
+    let messages = histories[0].history();
+    assert_eq!(messages.len(), 2);
+    assert_eq!((messages[0].author(), messages[0].content()), (String::from("You"), String::from("lunch?\n\nor later")));
+    assert_eq!(messages[0].status(), msg::MessageStatus::Delivered);
+    assert_eq!((messages[1].id(), messages[1].sent_at(), messages[1].received_at()), (2, 2000, 2001));
+
+    assert_eq!(histories[1].peer().ip(), "10.0.0.2");
+    assert!(histories[1].peer().private_key().is_none());
+}
+
+#[test]
+fn unknown_entries_are_skipped() {
+    let mut bytes = format::to_bytes(&[sample()]);
+    // an entry a later version might add, at the top level
+    bytes.extend_from_slice(&[99, 0, 0, 0, 3, 1, 2, 3]);
+    let histories = format::from_bytes(&bytes).unwrap();
+    assert_eq!(histories.len(), 1);
+    assert_eq!(histories[0].history().len(), 2);
+}
+
+#[test]
+fn broken_or_newer_files_are_refused() {
+    let bytes = format::to_bytes(&[sample()]);
+    assert!(format::from_bytes(&bytes[..bytes.len() - 1]).is_none(), "cut off files are refused");
+    assert!(format::from_bytes(&bytes[..4]).is_none());
+    assert!(format::from_bytes(b"10.0.0.2\0\0\0\n").is_none(), "legacy files are not read as the format");
+
+    let mut newer = bytes.clone();
+    newer[4..6].copy_from_slice(&(format::VERSION + 1).to_be_bytes());
+    assert!(format::from_bytes(&newer).is_none());
+    assert!(format::newer(&newer) && !format::newer(&bytes));
+    assert!(!format::newer(b"WHS"), "a damaged header is not taken for a newer version");
+    assert_eq!(format::from_bytes(&format::to_bytes(&[])).map(|histories| histories.len()), Some(0));
+}
+
+#[test]
+fn damaged_files_are_recovered() {
+    let intact = format::to_bytes(&[sample()]);
+    assert_eq!(format::recover(&intact).map(|(histories, skipped)| (histories.len(), skipped)), Some((1, 0)));
+
+    let mut other = msg::ChatHistory::new(msg::Recipient::from("10.0.0.2"));
+    other.push_msg(msg::Message::with_details(3, String::from("10.0.0.2"), String::from("intact"), 0, 0));
+    other.push_msg(msg::Message::with_details(4, String::from("10.0.0.2"), String::from("damaged"), 0, 0));
+    let mut bytes = format::to_bytes(&[other, sample()]);
+    // the content of the second message is no longer UTF-8
+    let at = bytes.windows(7).position(|window| window == b"damaged").unwrap();
+    bytes[at] = 0xff;
+    assert!(format::from_bytes(&bytes).is_none());
+    let (histories, skipped) = format::recover(&bytes).unwrap();
+    assert_eq!((histories.len(), skipped), (2, 1));
+    assert_eq!(histories[0].history().len(), 1, "the intact message of the damaged peer is kept");
+    assert_eq!(histories[1].history().len(), 2);
+
+    // a peer without a host is skipped, and so is a cut off tail
+    let mut bytes = format::to_bytes(&[sample()]);
+    format::put(&mut bytes, 1, &[]);
+    bytes.extend_from_slice(&intact[6..intact.len() - 1]);
+    let (histories, skipped) = format::recover(&bytes).unwrap();
+    assert_eq!((histories.len(), skipped), (1, 2));
+
+    let mut newer = intact.clone();
+    newer[4..6].copy_from_slice(&(format::VERSION + 1).to_be_bytes());
+    assert!(format::recover(&newer).is_none(), "newer files are left alone");
+    assert!(format::recover(b"10.0.0.2\0\0\0\n").is_none());
+}
+
+#[test]
+fn load_problems_say_what_was_kept() {
+    let damaged = Problem::Damaged {file: PathBuf::from("PEERS"), skipped: 2, backup: Some(PathBuf::from("PEERS.corrupt-20260101-120000"))};
+    assert_eq!(damaged.to_string(), "2 damaged records in PEERS were skipped, it was kept as PEERS.corrupt-20260101-120000");
+    let unreadable = Problem::Unreadable {file: PathBuf::from("JOURNAL"), backup: None};
+    assert_eq!(unreadable.to_string(), "JOURNAL could not be read, it could not be backed up");
+    let newer = Problem::Newer(PathBuf::from("PEERS"));
+    assert_eq!(newer.to_string(), "PEERS was saved by a newer version of Whisperer, nothing will be saved over it until you update");
+}
+
+#[test]
+fn legacy_files_are_read() {
+    // the bytes released versions wrote, with a 16 byte key that has a newline byte in it
+    let mut key = [7u8; 16];
+    key[3] = 10;
+    let mut recipients: Vec<u8> = b"10.0.0.2\0desk\0\n10.0.0.3\0\0".to_vec();
+    recipients.extend_from_slice(&key);
+    recipients.extend_from_slice(b"\n10.0.0.4\0\0\n");
+    let history = b"10.0.0.2\x012\n\0\x017 1000 1000 2\x02hi\n\x018 2000 2001\x02hello\nold line\n\n10.0.0.9\nlost\n\n10.0.0.3\n\0bye\n\n";
+
+    let (histories, orphans) = legacy::from_bytes(&recipients, history).unwrap();
+    assert_eq!(orphans, 1);
+    assert_eq!(histories.len(), 3);
+    let peer = histories[0].peer();
+    assert_eq!((peer.ip(), peer.port(), peer.alias()), (String::from("10.0.0.2"), tcp::DEFAULT_PORT, Some(String::from("desk"))));
+    assert_eq!(histories[0].unread(), 2);
+
+    let messages = histories[0].history();
+    assert_eq!(messages.len(), 3);
+    assert_eq!((messages[0].id(), messages[0].author(), messages[0].content()), (7, String::from("You"), String::from("hi")));
+    assert_eq!((messages[1].id(), messages[1].author(), messages[1].received_at()), (8, String::from("10.0.0.2"), 2001));
+    assert_eq!(messages[2].content(), "old line");
+
+    let keyed = histories[1].peer();
+    assert_eq!((keyed.ip(), keyed.alias(), keyed.private_key(), keyed.identity()), (String::from("10.0.0.3"), None, None, None), "the old key is dropped");
+    assert_eq!(histories[1].history()[0].author(), "You");
+    assert_eq!(histories[2].peer().ip(), "10.0.0.4");
+    assert!(histories[2].history().is_empty(), "histories of unknown peers are dropped");
+
+    let mut cut = recipients.clone();
+    cut.pop();
+    assert!(legacy::from_bytes(&cut, history).is_none(), "an entry that stops early makes the file damaged");
+    assert!(legacy::from_bytes(b"10.0.0.2\n", b"").is_none());
+}
+
+#[test]
+fn sealed_data_needs_the_passphrase() {
+    let contents = format::to_bytes(&[sample()]);
+    // cheap costs, so the test does not take seconds
+    let key = lock::Key::new("correct horse", 64, 1).unwrap();
+    let sealed = lock::seal(&contents, &key).unwrap();
+    assert!(lock::is_sealed(&sealed) && !lock::is_sealed(&contents));
+    assert!(!sealed.windows(4).any(|window| window == b"desk"), "nothing is kept in the clear");
+
+    let again = lock::Key::for_sealed("correct horse", &sealed).unwrap();
+    assert!(again.matches("correct horse") && !again.matches("correct horsf"));
+    assert_eq!(lock::open(&sealed, &again), Some(contents.clone()));
+    let wrong = lock::Key::for_sealed("correct horsf", &sealed).unwrap();
+    assert_eq!(lock::open(&sealed, &wrong), None);
+
+    let mut tampered = sealed.clone();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 1;
+    assert_eq!(lock::open(&tampered, &again), None);
+
+    let mut newer = sealed.clone();
+    newer[4..6].copy_from_slice(&(lock::VERSION + 1).to_be_bytes());
+    assert!(lock::Key::for_sealed("correct horse", &newer).is_none());
+    assert!(lock::Key::for_sealed("correct horse", &sealed[..10]).is_none());
+}
+
+/// A store that writes every change to an in-memory journal, the way `keep_saved` writes it to disk
+fn journaled(histories: Vec<msg::ChatHistory>, key: Option<lock::Key>) -> (Arc<PeerStore>, Arc<Mutex<Vec<u8>>>) {
+    let peers = Arc::new(PeerStore::new(histories));
+    let bytes = Arc::new(Mutex::new(journal::header()));
+    let (store, written) = (Arc::clone(&peers), Arc::clone(&bytes));
+    peers.watch(move |change| {
+        if let Some(record) = journal::Record::for_change(&store, &change) {
+            written.lock().unwrap().extend_from_slice(&journal::frame(&record, key.as_ref()));
+        }
+    });
+    (peers, bytes)
+}
+
+/// Apply `journal` to `snapshot`, and check it ends up like `peers`
+fn assert_replays(snapshot: &[msg::ChatHistory], journal: &[u8], key: Option<&lock::Key>, peers: &PeerStore) {
+    let mut histories = snapshot.to_vec();
+    let read = journal::read(journal, key);
+    assert!(!read.torn);
+    assert_eq!(read.intact, journal.len());
+    for record in read.records {
+        record.apply(&mut histories);
+    }
+    assert_eq!(format::to_bytes(&histories), format::to_bytes(&peers.histories()));
+}
+
+#[test]
+fn the_journal_replays_every_change() {
+    let snapshot = vec![sample()];
+    let (peers, journal) = journaled(snapshot.clone(), None);
+
+    let theirs = msg::Message::new(String::from("laptop.local"), String::from("back online"));
+    peers.push_msg("laptop.local:9999", theirs.clone());
+    let mut ours = msg::Message::new(String::from("You"), String::from("welcome back"));
+    ours.set_status(msg::MessageStatus::Pending);
+    peers.push_msg("laptop.local:9999", ours.clone());
+    peers.set_status("laptop.local:9999", ours.id(), msg::MessageStatus::Sent);
+    peers.modify("laptop.local:9999", |peer| peer.set_alias(Some(String::from("laptop"))));
+    peers.mark_read("laptop.local:9999");
+    peers.add(msg::Recipient::from("10.0.0.3"));
+    peers.push_msg("10.0.0.3", msg::Message::new(String::from("10.0.0.3"), String::from("hi")));
+    peers.clear_history("10.0.0.3");
+
+    let journal = journal.lock().unwrap().clone();
+    assert_replays(&snapshot, &journal, None, &peers);
+    // applying it again, on top of a save made after some of the changes, ends up the same
+    assert_replays(&peers.histories(), &journal, None, &peers);
+}
+
+#[test]
+fn moved_peers_keep_their_history_in_the_journal() {
+    let mut peer = msg::Recipient::from("10.0.0.2");
+    peer.set_identity(Some(vec![3u8; IDENTITY_SIZE]));
+    let mut history = msg::ChatHistory::new(peer);
+    history.push_msg(msg::Message::new(String::from("10.0.0.2"), String::from("before the move")));
+    let snapshot = vec![history];
+    let (peers, journal) = journaled(snapshot.clone(), None);
+
+    let mut moved = msg::Recipient::from("10.0.0.9");
+    moved.set_identity(Some(vec![3u8; IDENTITY_SIZE]));
+    peers.add(moved);
+    assert_eq!(peers.history("10.0.0.9").unwrap().history().len(), 1);
+
+    assert_replays(&snapshot, &journal.lock().unwrap(), None, &peers);
+}
+
+#[test]
+fn journals_naming_peers_by_host_still_apply() {
+    let mut other = msg::Recipient::from("laptop.local");
+    other.set_port(9000);
+    let mut histories = vec![sample(), msg::ChatHistory::new(other)];
+
+    let said = msg::Message::new(String::from("laptop.local"), String::from("from before"));
+    let records = [
+        journal::Record::Message {peer: journal::Name::Host(String::from("laptop.local")), unread: 2, msg: said.clone()},
+        journal::Record::Message {peer: journal::Name::Address(String::from("laptop.local:9000")), unread: 1, msg: said.clone()}
+    ];
+    for record in records {
+        let record = journal::Record::from_bytes(&record.to_bytes()).unwrap();
+        record.apply(&mut histories);
+    }
+    assert!(histories[0].find_msg(said.id()).is_some() && histories[0].unread() == 2);
+    assert!(histories[1].find_msg(said.id()).is_some() && histories[1].unread() == 1);
+
+    let removed = journal::Record::from_bytes(&journal::Record::Removed(journal::Name::Address(String::from("laptop.local:9000"))).to_bytes());
+    removed.unwrap().apply(&mut histories);
+    assert_eq!(histories.len(), 1);
+    journal::Record::Removed(journal::Name::Host(String::from("laptop.local"))).apply(&mut histories);
+    assert!(histories.is_empty());
+}
+
+#[test]
+fn torn_journal_frames_are_dropped() {
+    let (peers, journal) = journaled(vec![sample()], None);
+    peers.push_msg("laptop.local:9999", msg::Message::new(String::from("laptop.local"), String::from("kept")));
+    let intact = journal.lock().unwrap().len();
+    peers.push_msg("laptop.local:9999", msg::Message::new(String::from("laptop.local"), String::from("torn")));
+    let journal = journal.lock().unwrap().clone();
+
+    for cut in [intact + 3, journal.len() - 1] {
+        let read = journal::read(&journal[..cut], None);
+        assert!(read.torn);
+        assert_eq!((read.records.len(), read.intact), (1, intact));
+    }
+    let mut flipped = journal.clone();
+    let last = flipped.len() - 1;
+    flipped[last] ^= 1;
+    let read = journal::read(&flipped, None);
+    assert!(read.torn && read.records.len() == 1);
+    assert_eq!(read.skipped, 0);
+
+    assert!(journal::read(&journal[..3], None).torn, "a torn header drops the whole journal");
+    assert!(!journal::read(&[], None).torn);
+}
+
+#[test]
+fn sealed_journals_need_the_key() {
+    let key = lock::Key::new("correct horse", 64, 1).unwrap();
+    let snapshot = vec![sample()];
+    let (peers, journal) = journaled(snapshot.clone(), Some(key.clone()));
+    peers.push_msg("laptop.local:9999", msg::Message::new(String::from("laptop.local"), String::from("secret plans")));
+    let journal = journal.lock().unwrap().clone();
+    assert!(!journal.windows(6).any(|window| window == b"secret"));
+
+    assert_replays(&snapshot, &journal, Some(&key), &peers);
+    let read = journal::read(&journal, None);
+    assert!(read.records.is_empty() && !read.torn, "records that cannot be opened are not cut off");
+    let other = lock::Key::new("correct horsf", 64, 1).unwrap();
+    assert!(journal::read(&journal, Some(&other)).records.is_empty());
+}
@@ -0,0 +1,61 @@
+//! The errors of the network layer
+use crate::kem;
+use std::{fmt, io};
+
+#[derive(Debug)]
+pub enum Error {
+    /// Connecting, reading, writing, or a file on this end failed
+    Io(io::Error),
+    /// The other end sent something that does not follow the protocol
+    Protocol(&'static str),
+    /// The other end refused the request
+    Rejected,
+    /// A key, message or file failed authentication
+    Authentication,
+    /// The other end presented a different identity than the one pinned for it
+    IdentityMismatch,
+    /// The other end has pinned a different identity for this computer
+    IdentityRefused,
+    /// The other end declined a file
+    Declined,
+    /// A file did not arrive intact
+    Corrupted,
+    /// Nothing could listen on the port
+    Listen(u16, io::Error),
+    /// The daemon could not do what it was asked, with its reason
+    Daemon(String)
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Protocol(reason) => write!(f, "{reason}"),
+            Self::Rejected => write!(f, "Receiving end rejected the request"),
+            Self::Authentication => write!(f, "Authentication failed"),
+            Self::IdentityMismatch => write!(f, "Other end presented a different identity than the one pinned for it"),
+            Self::IdentityRefused => write!(f, "Receiving end has pinned a different identity for this computer"),
+            Self::Declined => write!(f, "Receiving end declined the file"),
+            Self::Corrupted => write!(f, "File did not arrive intact"),
+            Self::Listen(port, e) => write!(f, "Could not listen on port {port}: {e}"),
+            Self::Daemon(reason) => write!(f, "{reason}")
+        }
+    }
+}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) | Self::Listen(_, e) => Some(e),
+            _ => None
+        }
+    }
+}
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+impl From<kem::Error> for Error {
+    fn from(_: kem::Error) -> Self {
+        Self::Authentication
+    }
+}
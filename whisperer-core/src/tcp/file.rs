@@ -0,0 +1,182 @@
+#![allow(unused)]
+//! File transfers on top of the framed protocol
+//!
+//! The sender opens with a `File` frame holding an encrypted `Offer`. Once the other end answers
+//! with an empty response, the file follows as encrypted `File` frames of `FILE_CHUNK_SIZE` bytes,
+//! each starting with its index so chunks cannot be dropped or reordered. After the last chunk the
+//! receiving end checks the SHA-256 checksum from the offer and answers one last time.
+use super::{read_frame, write_frame, null_response, Error, Protocol, StreamReader};
+use crate::kem;
+use std::{
+    fs::{self, File},
+    io::{self, prelude::*, ErrorKind},
+    net::{TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    time::Duration
+};
+use sha2::{Digest, Sha256};
+
+/// The size of the plaintext in each chunk of a file
+pub const FILE_CHUNK_SIZE: usize = 64 * 1024;
+/// How long the receiving end has to accept or decline a file
+pub const OFFER_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The metadata sent before a file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Offer {
+    pub name: String,
+    pub size: u64,
+    pub checksum: Vec<u8>
+}
+impl Offer {
+    /// Build an offer for the file at `path`, hashing its contents.
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let name = path.file_name()
+            .ok_or(io::Error::new(ErrorKind::InvalidInput, "Path does not point at a file"))?
+            .to_string_lossy()
+            .to_string();
+
+        let mut file = File::open(path)?;
+        let mut hasher = Sha256::new();
+        let mut size = 0u64;
+        let mut chunk = vec![0u8; FILE_CHUNK_SIZE];
+        loop {
+            let read = file.read(&mut chunk)?;
+            if read == 0 {break}
+            hasher.update(&chunk[..read]);
+            size += read as u64;
+        }
+
+        Ok(Self {name, size, checksum: hasher.finalize().to_vec()})
+    }
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let name = self.name.as_bytes();
+        [
+            (name.len() as u16).to_be_bytes().as_slice(),
+            name,
+            &self.size.to_be_bytes(),
+            &self.checksum
+        ].concat()
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (length, rest) = bytes.split_at_checked(2)?;
+        let length = u16::from_be_bytes(length.try_into().ok()?) as usize;
+        let (name, rest) = rest.split_at_checked(length)?;
+        let (size, checksum) = rest.split_at_checked(8)?;
+        if checksum.len() != 32 {
+            return None;
+        }
+
+        Some(Self {
+            name: String::from_utf8(name.to_vec()).ok()?,
+            size: u64::from_be_bytes(size.try_into().ok()?),
+            checksum: checksum.to_vec()
+        })
+    }
+}
+
+/// Offer the file at `path` to `addr` and stream it if the other end accepts.
+/// `progress` is called with the amount of bytes sent so far.
+pub fn send_file(addr: impl ToSocketAddrs, path: &Path, key: Vec<u8>, mut progress: impl FnMut(u64)) -> Result<(), Error> {
+    let offer = Offer::from_path(path)?;
+    let sealed = kem::encrypt(offer.to_bytes(), key.clone())?;
+
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(OFFER_TIMEOUT + Duration::from_secs(10)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(30)))?;
+
+    write_frame(&mut stream, Protocol::File, &sealed)?;
+    expect_response(&mut stream)?;
+
+    stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+    let mut file = File::open(path)?;
+    let mut chunk = vec![0u8; FILE_CHUNK_SIZE];
+    let mut sent = 0u64;
+    let mut index = 0u64;
+    while sent < offer.size {
+        let read = file.read(&mut chunk)?;
+        if read == 0 {
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, "File got shorter while sending it").into());
+        }
+        let sealed = kem::encrypt([index.to_be_bytes().as_slice(), &chunk[..read]].concat(), key.clone())?;
+        write_frame(&mut stream, Protocol::File, &sealed)?;
+
+        sent += read as u64;
+        index += 1;
+        progress(sent);
+    }
+
+    expect_response(&mut stream)
+}
+
+/// Receive the file announced by `offer` into `dest`, after the offer was accepted.
+/// The file is written to `partial_path(dest)` first and only moved into place once its checksum matches.
+pub fn receive_file(stream: &mut TcpStream, offer: &Offer, key: Vec<u8>, dest: &Path, mut progress: impl FnMut(u64)) -> Result<(), Error> {
+    stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+    stream.respond(&[0u8])?;
+
+    let partial = partial_path(dest);
+    let result = receive_chunks(stream, offer, key, &partial, &mut progress);
+
+    match result {
+        Ok(()) => {
+            fs::rename(&partial, dest)?;
+            stream.respond(&[0u8])
+        },
+        Err(e) => {
+            fs::remove_file(&partial).unwrap_or(());
+            if !matches!(e, Error::Io(_)) {
+                stream.respond(&[5u8]).unwrap_or(());
+            }
+            Err(e)
+        }
+    }
+}
+
+fn receive_chunks(stream: &mut TcpStream, offer: &Offer, key: Vec<u8>, partial: &Path, progress: &mut impl FnMut(u64)) -> Result<(), Error> {
+    let mut file = File::create(partial)?;
+    let mut hasher = Sha256::new();
+    let mut received = 0u64;
+    let mut index = 0u64;
+
+    while received < offer.size {
+        let (protocol, data) = read_frame(stream)?;
+        if protocol != Protocol::File {
+            return Err(Error::Protocol("Sending end stopped sending the file"));
+        }
+        let data = kem::decrypt(data, key.clone())?;
+        if data.len() < 8 || data[..8] != index.to_be_bytes() {
+            return Err(Error::Corrupted);
+        }
+        let chunk = &data[8..];
+        if received + chunk.len() as u64 > offer.size {
+            return Err(Error::Corrupted);
+        }
+
+        file.write_all(chunk)?;
+        hasher.update(chunk);
+        received += chunk.len() as u64;
+        index += 1;
+        progress(received);
+    }
+    file.flush()?;
+
+    if hasher.finalize().as_slice() != offer.checksum.as_slice() {
+        return Err(Error::Corrupted);
+    }
+    Ok(())
+}
+
+/// Where a file headed for `dest` is written while it is still being received
+pub fn partial_path(dest: &Path) -> PathBuf {
+    let mut partial = dest.as_os_str().to_owned();
+    partial.push(".part");
+    PathBuf::from(partial)
+}
+
+fn expect_response(stream: &mut TcpStream) -> Result<(), Error> {
+    match read_frame(stream)? {
+        (Protocol::Response, response) => null_response(&response),
+        _ => Err(Error::Protocol("Receiving end did not send a response"))
+    }
+}
@@ -0,0 +1,246 @@
+#![allow(unused)]
+//! Wrappers around TcpStream with khyernet's custom protocol
+//! 
+//! This module will not work properly unless both ends are using the same protocol
+//! 
+//! Every transfer is a frame: a version byte, a command byte, the total payload length as a
+//! big-endian u32, and then the payload itself, streamed in chunks of `CHUNK_SIZE` bytes.
+//! Every request is answered with a `Response` frame.
+//! 
+//! Other tools can use this module through `whisperer-core` to talk to Whisperer directly
+pub mod vector;
+pub mod file;
+mod error;
+
+pub use error::Error;
+use vector as vect;
+use std::{
+    io::prelude::*,
+    net::{TcpStream, ToSocketAddrs, UdpSocket},
+    time::Duration
+};
+use crate::kem;
+
+/// The port Whisperer listens on unless it is configured otherwise
+pub const DEFAULT_PORT: u16 = 9998;
+/// The version of the wire format, sent as the first byte of every frame
+pub const PROTOCOL_VERSION: u8 = 1;
+/// The size of a frame header: version, command and payload length
+pub const HEADER_SIZE: usize = 6;
+/// The maximum payload length of a frame, longer frames are rejected before anything is read
+pub const MAX_CONTENT_LENGTH: usize = 64 * 1024 * 1024;
+/// The size of the chunks a payload is streamed in
+pub const CHUNK_SIZE: usize = 16 * 1024;
+
+pub trait StreamReader {
+    /// Handle incoming data. You are expected to respond to `PublicKey` by returning your public key.
+    /// You are also expected to build your private key from the public key sent with `CombineKey`.
+    /// Fails if no valid frame arrives, otherwise returns whatever `action` returns.
+    fn parse_incoming(&mut self, action: impl FnOnce(&mut Self, Protocol, Vec<u8>) -> Result<(), Error>) -> Result<(), Error>;
+    /// Answer the frame that was handled with a `Response` frame
+    fn respond(&mut self, payload: &[u8]) -> Result<(), Error>;
+}
+impl StreamReader for TcpStream {
+    fn parse_incoming(&mut self, action: impl FnOnce(&mut Self, Protocol, Vec<u8>) -> Result<(), Error>) -> Result<(), Error> {
+        let (protocol, data) = read_frame(self)?;
+
+        match protocol {
+            Protocol::Ping => self.respond(&[6u8]),
+            _ => action(self, protocol, data)
+        }
+    }
+    fn respond(&mut self, payload: &[u8]) -> Result<(), Error> {
+        write_frame(self, Protocol::Response, payload)
+    }
+}
+
+/// Write one frame carrying `payload` to `stream`.
+pub fn write_frame(stream: &mut impl Write, protocol: Protocol, payload: &[u8]) -> Result<(), Error> {
+    write_raw_frame(stream, protocol.code(), payload)
+}
+/// Write one frame with any command byte, for protocols that are framed the same way as this one
+pub fn write_raw_frame(stream: &mut impl Write, command: u8, payload: &[u8]) -> Result<(), Error> {
+    if payload.len() > MAX_CONTENT_LENGTH {
+        return Err(Error::Protocol("Payload is too long for a single frame"));
+    }
+
+    let mut header = [0u8; HEADER_SIZE];
+    header[0] = PROTOCOL_VERSION;
+    header[1] = command;
+    header[2..].copy_from_slice(&(payload.len() as u32).to_be_bytes());
+
+    stream.write_all(&header)?;
+    for chunk in payload.chunks(CHUNK_SIZE) {
+        stream.write_all(chunk)?;
+    }
+    Ok(stream.flush()?)
+}
+
+/// Read exactly one frame from `stream`, waiting for the whole payload to arrive.
+/// The payload is read chunk by chunk, so memory only grows as fast as data actually arrives.
+pub fn read_frame(stream: &mut impl Read) -> Result<(Protocol, Vec<u8>), Error> {
+    let (command, payload) = read_raw_frame(stream)?;
+    Ok((Protocol::from(command), payload))
+}
+/// Read exactly one frame with any command byte, see `write_raw_frame`
+pub fn read_raw_frame(stream: &mut impl Read) -> Result<(u8, Vec<u8>), Error> {
+    let mut header = [0u8; HEADER_SIZE];
+    stream.read_exact(&mut header)?;
+
+    if header[0] != PROTOCOL_VERSION {
+        return Err(Error::Protocol("Other end uses an unsupported protocol version"));
+    }
+    let length = u32::from_be_bytes([header[2], header[3], header[4], header[5]]) as usize;
+    if length > MAX_CONTENT_LENGTH {
+        return Err(Error::Protocol("Frame is longer than the maximum content length"));
+    }
+
+    let mut payload: Vec<u8> = Vec::with_capacity(length.min(CHUNK_SIZE));
+    let mut chunk = [0u8; CHUNK_SIZE];
+    while payload.len() < length {
+        let size = (length - payload.len()).min(CHUNK_SIZE);
+        stream.read_exact(&mut chunk[..size])?;
+        payload.extend_from_slice(&chunk[..size]);
+    }
+
+    Ok((header[1], payload))
+}
+
+/// Send one frame to `addr` and wait for the `Response` frame that answers it.
+fn request(addr: impl ToSocketAddrs, protocol: Protocol, payload: &[u8], timeout: Duration) -> Result<Vec<u8>, Error> {
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    write_frame(&mut stream, protocol, payload)?;
+
+    match read_frame(&mut stream)? {
+        (Protocol::Response, response) => Ok(response),
+        _ => Err(Error::Protocol("Receiving end did not send a response"))
+    }
+}
+
+/// Check if `addr` has an open port.
+pub fn check_availability(addr: impl ToSocketAddrs) -> Result<(), Error> {
+    let ack = request(addr, Protocol::Ping, &[], Duration::from_secs(5))?;
+
+    ack_response(&ack)
+}
+/// Send an encrypted message using khyernet's custom protocol.
+/// The message ID is sent in the clear in front of the ciphertext, so the other end can ask
+/// for a resend of it even when it has no key to decrypt it with.
+pub fn encrypted_send(addr: impl ToSocketAddrs, id: u64, message: &[u8], key: Vec<u8>) -> Result<Delivery, Error> {
+    let bytes = kem::encrypt(message, key)?;
+
+    let receipt = request(addr, Protocol::Message, &[id.to_be_bytes().as_slice(), &bytes].concat(), Duration::from_secs(10))?;
+
+    receipt_response(&receipt, id)
+}
+/// The response to a `Message` frame that was decrypted and shown, carrying the message ID.
+pub fn delivery_receipt(id: u64) -> Vec<u8> {
+    [[0u8].as_slice(), &id.to_be_bytes()].concat()
+}
+/// Split the payload of a `Message` frame into the message ID and the ciphertext.
+pub fn split_message(data: &[u8]) -> Option<(u64, Vec<u8>)> {
+    let (id, sealed) = data.split_at_checked(8)?;
+    Some((u64::from_be_bytes(id.try_into().ok()?), sealed.to_vec()))
+}
+/// Send a public key to the other end, expect the other end's public key back.
+pub fn send_public_key(addr: impl ToSocketAddrs, key: Vec<u8>) -> Result<Vec<u8>, Error> {
+    request(addr, Protocol::PublicKey, &key, Duration::from_secs(10))
+}
+/// Send our public key to the other end again, expect the other end to form their private key.
+pub fn send_mixed_key(addr: impl ToSocketAddrs, key: Vec<u8>) -> Result<(), Error> {
+    let empty = request(addr, Protocol::CombineKey, &key, Duration::from_secs(10))?;
+
+    null_response(&empty)
+}
+
+/// Request the other end to re-send the message with `id`
+pub fn request_resend(addr: impl ToSocketAddrs, id: u64) -> Result<(), Error> {
+    let empty = request(addr, Protocol::Resend, &id.to_be_bytes(), Duration::from_secs(10))?;
+
+    null_response(&empty)
+}
+
+/// How far a message got on the other end
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Delivery {
+    /// The other end decrypted and showed the message
+    Delivered,
+    /// The other end took the message but has no key for it yet, it will ask for a resend
+    Accepted
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    Ping, PublicKey, CombineKey, Message, Resend, File, Response, Unknown
+}
+impl Protocol {
+    /// The command byte sent in the frame header
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::Ping => 22,
+            Self::PublicKey => 1,
+            Self::CombineKey => 2,
+            Self::Message => 3,
+            Self::Resend => 4,
+            Self::File => 5,
+            Self::Response => 6,
+            Self::Unknown => 0
+        }
+    }
+}
+impl From<u8> for Protocol {
+    fn from(value: u8) -> Self {
+        match value {
+            22 => Self::Ping,
+            1 => Self::PublicKey,
+            2 => Self::CombineKey,
+            3 => Self::Message,
+            4 => Self::Resend,
+            5 => Self::File,
+            6 => Self::Response,
+            _ => Self::Unknown
+        }
+    }
+}
+
+fn null_response(response: &[u8]) -> Result<(), Error> {
+    match response {
+        [0u8] => Ok(()),
+        [1u8] => Err(Error::Rejected),
+        [2u8] => Err(Error::Authentication),
+        [3u8] => Err(Error::IdentityRefused),
+        [4u8] => Err(Error::Declined),
+        [5u8] => Err(Error::Corrupted),
+        _ => Err(Error::Protocol("Receiving end responded incorrectly"))
+    }
+}
+
+fn receipt_response(response: &[u8], id: u64) -> Result<Delivery, Error> {
+    match response {
+        [0u8] => Ok(Delivery::Accepted),
+        [0u8, receipt @ ..] if receipt == id.to_be_bytes() => Ok(Delivery::Delivered),
+        [0u8, ..] => Err(Error::Protocol("Receiving end acknowledged a different message")),
+        _ => null_response(response).and(Ok(Delivery::Accepted))
+    }
+}
+
+fn ack_response(response: &[u8]) -> Result<(), Error> {
+    match response {
+        [6u8] => Ok(()),
+        _ => Err(Error::Protocol("Receiving end did not acknowledge"))
+    }
+}
+
+/// The address this computer uses on the local network, found by routing a socket towards the internet.
+/// Nothing is sent.
+pub fn get_local_ip() -> Result<String, Error> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect("8.8.8.8:80")?;
+    Ok(socket.local_addr()?.ip().to_string())
+}
+
+#[cfg(test)]
+mod tests;
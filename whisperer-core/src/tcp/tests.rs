@@ -0,0 +1,340 @@
+#![cfg(test)]
+
+use super::*;
+use std::{
+    net::TcpListener,
+    io::{Cursor, Write},
+    thread,
+    sync::{Arc, Mutex}
+};
+
+#[test]
+fn sending_receiving() {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap();
+    let test_addr = port.local_addr().unwrap();
+    
+    let mut threads: Vec<thread::JoinHandle<()>> = Vec::new();
+    let sent = Arc::new(Mutex::new(String::new()));
+    let received = Arc::new(Mutex::new(String::new()));
+
+    let to_send = Arc::clone(&sent);
+    let sending = thread::spawn(move || {
+        check_availability(test_addr).unwrap();
+
+        let secret = kem::generate_secret();
+        let public_key = kem::public_key(&secret);
+        let recv_key = send_public_key(test_addr, public_key.clone()).unwrap();
+
+        let private_key = kem::agree(&secret, &recv_key, &public_key, &recv_key).unwrap();
+        println!("{:?}", private_key.clone());
+        
+        send_mixed_key(test_addr, public_key).unwrap();
+
+        let message = "you will be forever alone, für immer allein, 永远孤独 🥲";
+
+        let mut mutex = to_send.lock().unwrap();
+        *mutex = message.to_string();
+        drop(mutex);
+
+        let message = crate::msg::Message::new(String::from("You"), message.to_string());
+        let delivery = encrypted_send(test_addr, message.id(), &message.to_bytes(), private_key).unwrap();
+        assert_eq!(delivery, Delivery::Delivered);
+    });
+    threads.push(sending);
+    
+    let base_key = Arc::new(kem::generate_secret());
+    let private_key: Arc<Mutex<Vec<u8>>> = Arc::new(Mutex::new(Vec::new()));
+
+    let mut test_count: u8 = 0;
+
+    for req in port.incoming() {
+        let base_key = Arc::clone(&base_key);
+        let private_key = Arc::clone(&private_key);
+
+        let to_recv = Arc::clone(&received);
+        let handle = thread::spawn(move || {
+            let mut stream = req.unwrap();
+
+            stream.parse_incoming(|stream, protocol, data| match protocol {
+                Protocol::PublicKey => {
+                    let public_key = kem::public_key(&base_key);
+                    stream.respond(&public_key)
+                },
+                Protocol::CombineKey => {
+                    let public_key = kem::public_key(&base_key);
+                    let mut mutex = private_key.lock().unwrap();
+                    *mutex = kem::agree(&base_key, &data, &data, &public_key).unwrap();
+                    drop(mutex);
+
+                    println!("{:?}", &private_key);
+                    stream.respond(&[0u8])
+                },
+                Protocol::Message => {
+                    let key = {
+                        let mutex = private_key.lock().unwrap();
+                        mutex.clone()
+                    };
+
+                    let (id, data) = split_message(&data).unwrap();
+                    let message = kem::decrypt(data, key).unwrap();
+                    let message = crate::msg::Message::from_bytes(String::from("127.0.0.1"), &message).unwrap();
+                    assert_eq!(message.id(), id);
+                    let message = message.content();
+                    
+                    let mut mutex = to_recv.lock().unwrap();
+                    *mutex = message.to_string();
+                    drop(mutex);
+
+                    stream.respond(&delivery_receipt(id))
+                },
+                _ => stream.respond(&[0u8])
+            }).unwrap();
+        });
+        threads.push(handle);
+        test_count += 1;
+        if test_count >= 4 {break}
+    }
+
+    for handle in threads {
+        handle.join().unwrap();
+    }
+
+    let (sent, received) = {
+        let mutex = sent.lock().unwrap();
+        let sent = mutex.clone();
+        
+        let mutex = received.lock().unwrap();
+        let received = mutex.clone();
+
+        (sent, received)
+    };
+    
+    assert_eq!(sent, received);
+}
+
+/// A reader that hands out one byte per `read`, like a very slow network
+struct Trickle(Cursor<Vec<u8>>);
+impl Read for Trickle {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let end = buf.len().min(1);
+        self.0.read(&mut buf[..end])
+    }
+}
+
+#[test]
+fn frames_survive_terminator_bytes() {
+    let payload = vec![255u8, 0, 255, 10, 255];
+    let mut wire: Vec<u8> = Vec::new();
+    write_frame(&mut wire, Protocol::Message, &payload).unwrap();
+    assert_eq!(wire.len(), HEADER_SIZE + payload.len());
+
+    let (protocol, data) = read_frame(&mut Cursor::new(wire)).unwrap();
+    assert_eq!(protocol, Protocol::Message);
+    assert_eq!(data, payload);
+}
+
+#[test]
+fn frames_survive_partial_reads() {
+    let payload = vect::rand_byte_vector(CHUNK_SIZE * 2 + 7);
+    let mut wire: Vec<u8> = Vec::new();
+    write_frame(&mut wire, Protocol::PublicKey, &payload).unwrap();
+
+    let (protocol, data) = read_frame(&mut Trickle(Cursor::new(wire))).unwrap();
+    assert_eq!(protocol, Protocol::PublicKey);
+    assert_eq!(data, payload);
+}
+
+#[test]
+fn bad_frames_are_rejected() {
+    let mut wire: Vec<u8> = Vec::new();
+    write_frame(&mut wire, Protocol::Message, b"hello").unwrap();
+
+    let mut wrong_version = wire.clone();
+    wrong_version[0] = PROTOCOL_VERSION + 1;
+    assert!(matches!(read_frame(&mut Cursor::new(wrong_version)), Err(Error::Protocol(_))));
+
+    let truncated = wire[..wire.len() - 1].to_vec();
+    assert!(matches!(read_frame(&mut Cursor::new(truncated)), Err(Error::Io(_))));
+
+    let mut too_long = wire.clone();
+    too_long[2..HEADER_SIZE].copy_from_slice(&(MAX_CONTENT_LENGTH as u32 + 1).to_be_bytes());
+    assert!(matches!(read_frame(&mut Cursor::new(too_long)), Err(Error::Protocol(_))));
+
+    let mut unsent: Vec<u8> = Vec::new();
+    assert!(matches!(write_frame(&mut unsent, Protocol::Message, &vec![0u8; MAX_CONTENT_LENGTH + 1]), Err(Error::Protocol(_))));
+    assert!(unsent.is_empty(), "nothing is written for a payload that is too long");
+}
+
+#[test]
+fn large_payloads_are_streamed() {
+    let payload = vect::rand_byte_vector(CHUNK_SIZE * 40 + 123);
+    let mut wire: Vec<u8> = Vec::new();
+    write_frame(&mut wire, Protocol::Message, &payload).unwrap();
+    assert_eq!(u32::from_be_bytes(wire[2..HEADER_SIZE].try_into().unwrap()) as usize, payload.len());
+
+    let (_, data) = read_frame(&mut Cursor::new(wire)).unwrap();
+    assert_eq!(data, payload);
+}
+
+#[test]
+fn multi_byte_strings() {
+    let message = "héllo wörld, こんにちは, 안녕하세요, привет 👋🏽";
+    let bytes = vect::bytes_from_string(message);
+    assert_eq!(bytes.len(), message.len());
+    assert_eq!(vect::bytes_to_string(bytes), message);
+
+    let key = vect::rand_byte_vector(kem::KEY_SIZE);
+    let mut wire: Vec<u8> = Vec::new();
+    let sealed = kem::encrypt(vect::bytes_from_string(message), key.clone()).unwrap();
+    write_frame(&mut wire, Protocol::Message, &sealed).unwrap();
+
+    let (_, data) = read_frame(&mut Trickle(Cursor::new(wire))).unwrap();
+    let received = vect::bytes_to_string(kem::decrypt(data, key).unwrap());
+    assert_eq!(received, message);
+
+    let mut msg = crate::msg::Message::new(String::from("You"), format!("{message}\0\0"));
+    msg.clean_nulls();
+    assert_eq!(msg.content(), message);
+}
+
+#[test]
+fn longest_draft_fits_in_a_frame() {
+    let draft = "👋".repeat(100_000);
+    let sealed = kem::encrypt(vect::bytes_from_string(&draft), vect::rand_byte_vector(kem::KEY_SIZE)).unwrap();
+    assert!(write_frame(&mut Vec::new(), Protocol::Message, &sealed).is_ok());
+}
+
+#[test]
+fn invalid_utf8_is_replaced() {
+    assert_eq!(vect::bytes_to_string(vec![b'h', b'i', 0xC3]), "hi\u{FFFD}");
+}
+
+#[test]
+fn file_offers_round_trip() {
+    let offer = file::Offer {name: String::from("schönes bild.png"), size: 1234567, checksum: vec![7u8; 32]};
+    assert_eq!(file::Offer::from_bytes(&offer.to_bytes()), Some(offer.clone()));
+    assert_eq!(file::Offer::from_bytes(&offer.to_bytes()[..10]), None);
+}
+
+/// Accept one connection on an ephemeral port and answer a file offer with `accept`
+fn file_receiver(key: Vec<u8>, dest: std::path::PathBuf, accept: bool) -> (String, thread::JoinHandle<Result<(), Error>>) {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap();
+    let ip = port.local_addr().unwrap().to_string();
+
+    let handle = thread::spawn(move || {
+        let (mut stream, _) = port.accept().unwrap();
+        let mut result = Ok(());
+        stream.parse_incoming(|stream, protocol, data| {
+            assert_eq!(protocol, Protocol::File);
+            let offer = file::Offer::from_bytes(&kem::decrypt(data, key.clone()).unwrap()).unwrap();
+            result = match accept {
+                true => file::receive_file(stream, &offer, key, &dest, |_| ()),
+                false => stream.respond(&[4u8])
+            };
+            Ok(())
+        }).unwrap();
+        result
+    });
+    (ip, handle)
+}
+
+#[test]
+fn sending_files() {
+    let dir = std::env::temp_dir().join(format!("whisperer-test-{}", rand::random::<u64>()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("source.bin");
+    let dest = dir.join("dest.bin");
+    let contents = vect::rand_byte_vector(file::FILE_CHUNK_SIZE * 3 + 42);
+    std::fs::write(&source, &contents).unwrap();
+
+    let key = vect::rand_byte_vector(kem::KEY_SIZE);
+    let (ip, receiver) = file_receiver(key.clone(), dest.clone(), true);
+    let mut progress: Vec<u64> = Vec::new();
+    file::send_file(&ip, &source, key.clone(), |sent| progress.push(sent)).unwrap();
+    receiver.join().unwrap().unwrap();
+
+    assert_eq!(std::fs::read(&dest).unwrap(), contents);
+    assert_eq!(progress.last(), Some(&(contents.len() as u64)));
+    assert!(!file::partial_path(&dest).exists());
+
+    let (ip, receiver) = file_receiver(key.clone(), dir.join("declined.bin"), false);
+    let declined = file::send_file(&ip, &source, key, |_| ()).unwrap_err();
+    receiver.join().unwrap().unwrap();
+    assert!(matches!(declined, Error::Declined));
+    assert!(!dir.join("declined.bin").exists());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn messages_carry_ids_and_timestamps() {
+    let sent = crate::msg::Message::new(String::from("You"), String::from("ünïcode ✓"));
+    let received = crate::msg::Message::from_bytes(String::from("127.0.0.1"), &sent.to_bytes()).unwrap();
+
+    assert_eq!(received.id(), sent.id());
+    assert_eq!(received.sent_at(), sent.sent_at());
+    assert_eq!(received.content(), sent.content());
+    assert_eq!(received.author(), "127.0.0.1");
+    assert!(received.received_at() >= sent.sent_at());
+
+    assert_ne!(sent.id(), crate::msg::Message::new(String::from("You"), sent.content()).id());
+    assert!(crate::msg::Message::from_bytes(String::from("127.0.0.1"), &[0u8; 15]).is_none());
+}
+
+#[test]
+fn receipts_name_the_message() {
+    let id: u64 = rand::random();
+
+    assert_eq!(receipt_response(&delivery_receipt(id), id).unwrap(), Delivery::Delivered);
+    assert_eq!(receipt_response(&[0u8], id).unwrap(), Delivery::Accepted);
+    assert!(receipt_response(&delivery_receipt(id.wrapping_add(1)), id).is_err());
+    assert!(matches!(receipt_response(&[2u8], id), Err(Error::Authentication)));
+    assert!(receipt_response(&[], id).is_err());
+}
+
+#[test]
+fn hosts_are_validated() {
+    for host in ["192.168.1.20", "::1", "fe80::1%eth0", "2001:db8::7", "laptop.local", "my-pc", "example.com"] {
+        assert!(crate::msg::is_valid_host(host), "{host} should be valid");
+    }
+    for host in ["", "192.168.1", "256.1.1.1", "fe80::1%", "-pc", "pc-.local", "two..dots", "has space", "a:b"] {
+        assert!(!crate::msg::is_valid_host(host), "{host} should be invalid");
+    }
+
+    assert_eq!(crate::msg::host_ip("fe80::1%eth0"), Some("fe80::1".parse().unwrap()));
+    assert_eq!(crate::msg::host_ip("laptop.local"), None);
+}
+
+#[test]
+fn requests_reach_ipv6_hosts() {
+    let listener = TcpListener::bind("[::1]:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let handle = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.parse_incoming(|stream, _, _| stream.respond(&[1u8])).unwrap();
+    });
+
+    check_availability(("::1", port)).unwrap();
+    handle.join().unwrap();
+}
+
+#[test]
+fn addresses_carry_ports() {
+    use crate::msg::parse_address;
+
+    assert_eq!(parse_address("192.168.1.20"), Some((String::from("192.168.1.20"), DEFAULT_PORT)));
+    assert_eq!(parse_address("192.168.1.20:9999"), Some((String::from("192.168.1.20"), 9999)));
+    assert_eq!(parse_address("laptop.local:1234"), Some((String::from("laptop.local"), 1234)));
+    assert_eq!(parse_address("fe80::1%eth0"), Some((String::from("fe80::1%eth0"), DEFAULT_PORT)));
+    assert_eq!(parse_address("[fe80::1%eth0]:9999"), Some((String::from("fe80::1%eth0"), 9999)));
+    assert_eq!(parse_address("[::1]"), Some((String::from("::1"), DEFAULT_PORT)));
+    for address in ["192.168.1.20:0", "192.168.1.20:70000", "pc:", "[::1]9999", "[pc]:x"] {
+        assert_eq!(parse_address(address), None, "{address} should be invalid");
+    }
+
+    let mut peer = crate::msg::Recipient::from("fe80::1%eth0");
+    assert_eq!(peer.address(), "fe80::1%eth0");
+    peer.set_port(9999);
+    assert_eq!(peer.address(), "[fe80::1%eth0]:9999");
+    assert_eq!(parse_address(&peer.address()), Some((peer.ip(), peer.port())));
+}
@@ -0,0 +1,30 @@
+#![allow(unused)]
+use rand::Rng;
+
+pub fn rand_byte_vector(size: usize) -> Vec<u8> {
+    assert!(size > 0);
+    let mut vector: Vec<u8> = Vec::with_capacity(size);
+    let mut rng = rand::thread_rng();
+    for _ in 0..size {
+        vector.push(rng.gen_range(0..=255));
+    }
+    vector
+}
+
+/// Encode `string` as UTF-8
+pub fn bytes_from_string<S: ToString>(string: S) -> Vec<u8> {
+    string.to_string().into_bytes()
+}
+
+/// Decode UTF-8 `bytes`, invalid sequences are replaced with U+FFFD
+pub fn bytes_to_string<V: Into<Vec<u8>>>(bytes: V) -> String {
+    let bytes: Vec<u8> = bytes.into();
+    match String::from_utf8(bytes) {
+        Ok(string) => string,
+        Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned()
+    }
+}
+
+pub fn remove_null(bytes: Vec<u8>) -> Vec<u8> {
+    bytes.iter().filter(|b| **b != 0).copied().collect()
+}